@@ -0,0 +1,24 @@
+// Benchmarks of broadcasting events. The server has no clients, so these measure the cost of
+// building the event and getting it ready to send.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use hyper_usse::{CachedEvent, EventBuilder, Server};
+
+fn broadcast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broadcast");
+    let mut server = Server::new();
+    group.bench_function("send_to_clients", |b| {
+        b.iter(|| block_on(server.send_to_clients(EventBuilder::new(black_box("Some data")).id("1"))))
+    });
+    group.bench_function("broadcast_event", |b| {
+        b.iter(|| block_on(server.broadcast_event(&EventBuilder::new(black_box("Some data")).id("1"))))
+    });
+    let cached = CachedEvent::new(EventBuilder::new("Some data").id("1"));
+    group.bench_function("broadcast_cached", |b| {
+        b.iter(|| block_on(server.broadcast_cached(black_box(&cached))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, broadcast);
+criterion_main!(benches);
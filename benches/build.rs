@@ -0,0 +1,39 @@
+// Benchmarks of building events.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use bytes::{Bytes, BytesMut};
+use hyper_usse::EventBuilder;
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    group.bench_function("single line", |b| {
+        b.iter(|| EventBuilder::new(black_box("Some data for the clients")).build())
+    });
+    group.bench_function("single line with id", |b| {
+        b.iter(|| EventBuilder::new(black_box("Some data for the clients")).id("1").build())
+    });
+    group.bench_function("multi-line", |b| {
+        b.iter(|| EventBuilder::new(black_box("Some data\nfor the\nclients")).build())
+    });
+    group.finish();
+}
+
+fn build_into(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_into");
+    group.bench_function("string then bytes", |b| {
+        b.iter(|| Bytes::from(EventBuilder::new(black_box("Some data\nfor the\nclients")).build()))
+    });
+    group.bench_function("fresh buffer", |b| {
+        b.iter(|| Bytes::from(EventBuilder::new(black_box("Some data\nfor the\nclients"))))
+    });
+    let mut buf = BytesMut::new();
+    group.bench_function("reused buffer", |b| {
+        b.iter(|| {
+            EventBuilder::new(black_box("Some data\nfor the\nclients")).build_into(&mut buf);
+            buf.split().freeze()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, build, build_into);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+// Benchmarks of broadcasting to many clients at once, comparing sending to every client at once
+// with sending to a limited number at a time. Before timing, the peak memory used by a single
+// broadcast with each is printed.
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use hyper::body::Bytes;
+use hyper_usse::{EventBuilder, EventSink, Server, SinkError};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+const CLIENTS: usize = 10_000;
+const CONCURRENCY: usize = 64;
+
+/// An allocator that keeps track of the most memory allocated at once.
+struct PeakAlloc {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: PeakAlloc = PeakAlloc { current: AtomicUsize::new(0), peak: AtomicUsize::new(0) };
+
+/// A client that takes everything it is sent straight away and throws it away.
+#[derive(Debug)]
+struct Discard;
+
+impl EventSink for Discard {
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), SinkError>> {
+        Poll::Ready(Ok(()))
+    }
+    fn start_send(&mut self, _: Bytes) -> Result<(), SinkError> {
+        Ok(())
+    }
+    fn abort(self) {}
+}
+
+fn server() -> Server<Discard> {
+    let mut server = Server::default();
+    for _ in 0..CLIENTS {
+        server.add_client(Discard);
+    }
+    server
+}
+
+/// Get the most memory used at once while running `f`, beyond what was in use before it.
+fn peak_memory(f: impl FnOnce()) -> usize {
+    let before = ALLOC.current.load(Ordering::Relaxed);
+    ALLOC.peak.store(before, Ordering::Relaxed);
+    f();
+    ALLOC.peak.load(Ordering::Relaxed) - before
+}
+
+fn fan_out(c: &mut Criterion) {
+    let mut server = server();
+    let unbounded = peak_memory(|| {
+        block_on(server.send_to_clients(EventBuilder::new("Some data")));
+    });
+    let buffered = peak_memory(|| {
+        block_on(server.send_to_clients_buffered(EventBuilder::new("Some data"), CONCURRENCY));
+    });
+    println!("peak memory of a broadcast to {} clients:", CLIENTS);
+    println!("  send_to_clients: {} bytes", unbounded);
+    println!("  send_to_clients_buffered({}): {} bytes", CONCURRENCY, buffered);
+
+    let mut group = c.benchmark_group("fan_out");
+    group.bench_function("send_to_clients", |b| {
+        b.iter(|| block_on(server.send_to_clients(EventBuilder::new("Some data"))))
+    });
+    group.bench_function("send_to_clients_buffered", |b| {
+        b.iter(|| {
+            block_on(server.send_to_clients_buffered(EventBuilder::new("Some data"), CONCURRENCY))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, fan_out);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+// Example of forwarding an application's broadcast channel to SSE clients.
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response};
+use hyper_usse::EventBuilder;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time;
+
+const HTML: &str = r#"
+<!DOCTYPE html>
+<html>
+    <head>
+        <title>Hyper-usse Demo</title>
+        <meta charset="utf-8" />
+    </head>
+    <body>
+        <h1>Hyper-usse Demo</h1>
+        <p>Incoming events:</p>
+        <ul id="list"></ul>
+        <script>
+let server = new EventSource("http://localhost:8000/sse");
+server.onmessage = event => {
+    let elem = document.createElement("li");
+    elem.innerHTML = event.data;
+
+    document.getElementById("list").appendChild(elem);
+};
+        </script>
+    </body>
+</html>"#;
+
+async fn process_request(
+    sse: Arc<Mutex<hyper_usse::Server>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/") => Response::new(Body::from(HTML)),
+        (&Method::GET, "/sse") => {
+            let (channel, body) = Body::channel();
+            sse.lock().await.add_client(channel);
+            hyper_usse::response(body)
+        }
+        _ => Response::builder()
+            .status(404)
+            .body(Body::from("Not found."))
+            .unwrap(),
+    };
+    Ok(result)
+}
+
+// Some part of the application that publishes events to anyone interested.
+async fn produce_events(events: broadcast::Sender<EventBuilder<'static>>) {
+    let mut interval = time::interval(Duration::from_secs(3));
+    for count in 0.. {
+        interval.tick().await;
+        println!("Publishing event {}...", count);
+        let _ = events.send(EventBuilder::new(format!("Event number {}", count)));
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let sse = Arc::new(Mutex::new(hyper_usse::Server::new()));
+
+    let (events, receiver) = broadcast::channel(16);
+    hyper_usse::Server::pump_broadcast(Arc::clone(&sse), receiver, true);
+    tokio::spawn(produce_events(events));
+
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 8000).into()).serve(make_service_fn(|_| {
+        let sse = Arc::clone(&sse);
+
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |request: Request<Body>| {
+                process_request(Arc::clone(&sse), request)
+            }))
+        }
+    }));
+
+    println!("Go to http://localhost:8000/.");
+
+    if let Err(err) = server.await {
+        eprintln!("Server error: {}", err);
+    }
+}
@@ -41,11 +41,7 @@ async fn process_request(
         (&Method::GET, "/sse") => {
             let (channel, body) = Body::channel();
             sse.lock().await.add_client(channel);
-            Response::builder()
-                .header("Content-Type", "text/event-stream")
-                .header("Cache-Control", "no-cache")
-                .body(body)
-                .unwrap()
+            hyper_usse::response(body)
         }
         _ => Response::builder()
             .status(404)
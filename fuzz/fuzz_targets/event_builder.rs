@@ -0,0 +1,69 @@
+// Check that events built from arbitrary fields are always framed as exactly one event.
+#![no_main]
+use hyper_usse::EventBuilder;
+use libfuzzer_sys::fuzz_target;
+
+/// Count the lines the builder splits text into: `\r\n`, `\n` and a lone `\r` are all line breaks,
+/// and a single trailing line break doesn't start another line.
+fn count_lines(text: &str) -> usize {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    if text.is_empty() {
+        0
+    } else {
+        text.strip_suffix('\n').unwrap_or(&text).split('\n').count()
+    }
+}
+
+type Input<'a> = (
+    &'a str,
+    Option<&'a str>,
+    Option<&'a str>,
+    Vec<(&'a str, &'a str)>,
+    Vec<&'a str>,
+    Option<&'a str>,
+);
+
+fuzz_target!(|input: Input<'_>| {
+    let (data, id, event_type, fields, comments, data_field) = input;
+    let mut event = EventBuilder::new(data);
+    if let Some(id) = id {
+        event = event.id(id);
+    }
+    if let Some(event_type) = event_type {
+        event = event.event_type(event_type);
+    }
+    for comment in &comments {
+        event = event.append_comment_lines(*comment);
+    }
+    // Fields and the data field name are set directly, skipping the checks of `field` and
+    // `data_field_name`, as building must keep the framing even then.
+    event.fields = fields.iter().map(|&(name, value)| (name.into(), value.into())).collect();
+    event.data_field = data_field.map(Into::into);
+    let built = event.build();
+    assert_eq!(built.len(), event.encoded_len());
+
+    let data = data.strip_prefix('\u{FEFF}').unwrap_or(data);
+    let expected_lines = comments.iter().map(|comment| count_lines(comment)).sum::<usize>()
+        + usize::from(id.is_some())
+        + usize::from(event_type.is_some())
+        + fields.len()
+        + count_lines(data);
+
+    // An event with no lines at all is just the blank line that ends it, which doesn't dispatch
+    // anything.
+    if built == "\n" {
+        assert_eq!(expected_lines, 0);
+        return;
+    }
+
+    // Otherwise the event ends with exactly one blank line, and there is one line before it for
+    // every field, comment line and data line that was set; a lone `\r` would also be a line break
+    // to clients.
+    assert!(!built.contains('\r'));
+    let body = built.strip_suffix("\n\n").expect("event doesn't end with a blank line");
+    let lines: Vec<&str> = body.split('\n').collect();
+    assert_eq!(lines.len(), expected_lines, "wrong number of lines in {:?}", built);
+    for line in lines {
+        assert!(!line.is_empty(), "blank line in {:?}", built);
+    }
+});
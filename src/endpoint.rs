@@ -0,0 +1,264 @@
+//! Answering SSE requests, either with the helpers for building responses and reading requests
+//! or with a high level SSE endpoint.
+use hyper::body::{Body, Bytes};
+use hyper::header::ACCEPT;
+use hyper::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::server::Server;
+
+/// An SSE endpoint at a path, bundling a [Server](struct.Server.html) with the routing and response
+/// setup that every endpoint needs.
+///
+/// [`handle`](#method.handle) answers `GET` requests to the endpoint's path by adding a client to
+/// the server, replaying the events it missed if it sent the id of its last event, and leaves
+/// every other request to the rest of the application's router. Events are sent with
+/// [`broadcast`](#method.broadcast).
+///
+/// Cloning an endpoint is cheap, and the clones share the same server. To configure the server or
+/// use it directly, create it first and pass it to [`with_server`](#method.with_server), or lock
+/// [`server`](#method.server).
+///
+/// # Examples
+/// ```
+/// # futures::executor::block_on(async {
+/// # use hyper::{Body, Request};
+/// # use hyper_usse::{EventBuilder, SseEndpoint};
+/// let endpoint = SseEndpoint::new("/sse");
+///
+/// let request = Request::get("/sse").body(Body::empty()).unwrap();
+/// let response = endpoint.handle(&request).await.unwrap();
+/// assert_eq!(response.status(), 200);
+///
+/// let request = Request::get("/").body(Body::empty()).unwrap();
+/// assert!(endpoint.handle(&request).await.is_none());
+///
+/// let producer = async move {
+///     endpoint.broadcast(EventBuilder::new("Hello")).await;
+/// };
+/// let (_, received) = futures::join!(producer, hyper::body::to_bytes(response.into_body()));
+/// assert_eq!(received.unwrap(), "data: Hello\n\n");
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct SseEndpoint {
+    path: String,
+    server: Arc<Mutex<Server>>,
+}
+
+impl SseEndpoint {
+    /// Create an endpoint at `path` with a new server.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self::with_server(path, Server::new())
+    }
+
+    /// Create an endpoint at `path` that adds its clients to `server`.
+    pub fn with_server(path: impl Into<String>, server: Server) -> Self {
+        Self {
+            path: path.into(),
+            server: Arc::new(Mutex::new(server)),
+        }
+    }
+
+    /// Get the path the endpoint answers requests to.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the server the endpoint adds its clients to.
+    pub fn server(&self) -> &Arc<Mutex<Server>> {
+        &self.server
+    }
+
+    /// Answer a request if it is for the endpoint, returning `None` if it isn't so that the
+    /// application can route it elsewhere.
+    ///
+    /// A `GET` request to the endpoint's path gets an SSE [response](fn.response.html), and the
+    /// client is added to the server with the last event id found by
+    /// [extract_last_event_id](fn.extract_last_event_id.html), if there is one. If the request's
+    /// `Accept` header doesn't allow SSE (see [accepts_sse](fn.accepts_sse.html)), it gets a
+    /// `406 Not Acceptable` response instead. Requests to other paths or with other methods aren't
+    /// answered.
+    pub async fn handle<B>(&self, request: &Request<B>) -> Option<Response<Body>> {
+        if request.method() != Method::GET || request.uri().path() != self.path {
+            return None;
+        }
+        if !accepts_sse(request) {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_ACCEPTABLE;
+            return Some(response);
+        }
+        let last_event_id = extract_last_event_id(request);
+        let (client, body) = Body::channel();
+        self.server.lock().await.add_client_with_last_id(client, last_event_id.as_deref());
+        Some(response(body))
+    }
+
+    /// Send some text to the endpoint's clients. See
+    /// [`Server::send_to_clients`](struct.Server.html#method.send_to_clients).
+    pub async fn broadcast<B: Into<Bytes>>(&self, text: B) -> usize {
+        self.server.lock().await.send_to_clients(text).await
+    }
+}
+
+/// Create the response to an SSE request, streaming `body` to the client. `body` can be obtained
+/// by calling `Body::channel()`.
+///
+/// The response has the `Content-Type: text/event-stream; charset=utf-8` and
+/// `Cache-Control: no-cache` headers. Event streams are always UTF-8, but some clients and proxies
+/// guess the encoding unless the charset is given. Either header can be overridden by replacing it
+/// in the response's headers.
+///
+/// Don't give it a `Content-Length`; the stream has to be sent chunked, otherwise clients will only
+/// see the events once the connection closes. Similarly, if responses pass through a compression
+/// layer it must flush after every chunk, so it is usually best to not compress event streams.
+///
+/// # Examples
+/// ```
+/// # use hyper::header::{HeaderValue, CONTENT_TYPE};
+/// let mut response = hyper_usse::response(hyper::Body::empty());
+/// assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream; charset=utf-8");
+///
+/// response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+/// assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+/// ```
+pub fn response(body: Body) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "text/event-stream; charset=utf-8")
+        .header("Cache-Control", "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+/// Check whether a request's `Accept` header allows a `text/event-stream` response, so that
+/// clients which can't handle SSE can be sent `406 Not Acceptable` instead.
+///
+/// `text/event-stream`, `text/*` and `*/*` all match, and the most specific match decides, so a
+/// match with `q=0` rejects SSE even if a less specific range accepts it. A request with no
+/// `Accept` header accepts anything.
+///
+/// # Examples
+/// ```
+/// # use hyper::Request;
+/// # use hyper_usse::accepts_sse;
+/// let request = |accept| Request::get("/sse").header("Accept", accept).body(()).unwrap();
+///
+/// assert!(accepts_sse(&request("text/event-stream")));
+/// assert!(accepts_sse(&request("application/json, text/*;q=0.5")));
+/// assert!(accepts_sse(&request("*/*")));
+/// assert!(!accepts_sse(&request("application/json")));
+/// assert!(!accepts_sse(&request("text/event-stream;q=0, */*")));
+/// assert!(accepts_sse(&Request::get("/sse").body(()).unwrap()));
+/// ```
+pub fn accepts_sse<B>(request: &Request<B>) -> bool {
+    let mut best: Option<(u8, bool)> = None;
+    let mut has_accept = false;
+    for header in request.headers().get_all(ACCEPT) {
+        has_accept = true;
+        let header = match header.to_str() {
+            Ok(header) => header,
+            Err(_) => continue,
+        };
+        for range in header.split(',') {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or("").trim();
+            let specificity = if media_type.eq_ignore_ascii_case("text/event-stream") {
+                3
+            } else if media_type.eq_ignore_ascii_case("text/*") {
+                2
+            } else if media_type == "*/*" {
+                1
+            } else {
+                continue;
+            };
+            let accepted = !parts.any(|param| {
+                let mut param = param.splitn(2, '=');
+                let name = param.next().unwrap_or("").trim();
+                let value = param.next().unwrap_or("").trim();
+                name.eq_ignore_ascii_case("q") && value.parse::<f32>() == Ok(0.0)
+            });
+            if best.map_or(true, |(best, _)| specificity > best) {
+                best = Some((specificity, accepted));
+            }
+        }
+    }
+    match best {
+        Some((_, accepted)) => accepted,
+        None => !has_accept,
+    }
+}
+
+/// The query parameter [`extract_last_event_id`](fn.extract_last_event_id.html) falls back to.
+pub const LAST_EVENT_ID_PARAM: &str = "lastEventId";
+
+/// Get the id of the last event a reconnecting client received, to pass to
+/// [`Server::add_client_with_last_id`](struct.Server.html#method.add_client_with_last_id).
+///
+/// Browsers send it in the `Last-Event-ID` header, but polyfills and client libraries that can't
+/// set headers pass it as the `lastEventId` query parameter instead, so both are checked. The
+/// header takes precedence when a request has both. Use
+/// [`extract_last_event_id_from`](fn.extract_last_event_id_from.html) for a different query
+/// parameter.
+///
+/// # Examples
+/// ```
+/// # use hyper::Request;
+/// # use hyper_usse::extract_last_event_id;
+/// let request = Request::get("/sse").header("Last-Event-ID", "7").body(()).unwrap();
+/// assert_eq!(extract_last_event_id(&request).as_deref(), Some("7"));
+///
+/// let request = Request::get("/sse?lastEventId=a%20b").body(()).unwrap();
+/// assert_eq!(extract_last_event_id(&request).as_deref(), Some("a b"));
+///
+/// let request = Request::get("/sse?lastEventId=1").header("Last-Event-ID", "2").body(()).unwrap();
+/// assert_eq!(extract_last_event_id(&request).as_deref(), Some("2"));
+///
+/// assert_eq!(extract_last_event_id(&Request::get("/sse").body(()).unwrap()), None);
+/// ```
+pub fn extract_last_event_id<B>(request: &Request<B>) -> Option<String> {
+    extract_last_event_id_from(request, LAST_EVENT_ID_PARAM)
+}
+
+/// Get the id of the last event a reconnecting client received like
+/// [`extract_last_event_id`](fn.extract_last_event_id.html), falling back to the query parameter
+/// `param` instead of `lastEventId`. The parameter's value is percent-decoded.
+pub fn extract_last_event_id_from<B>(request: &Request<B>, param: &str) -> Option<String> {
+    if let Some(id) = request.headers().get("Last-Event-ID").and_then(|id| id.to_str().ok()) {
+        return Some(id.to_owned());
+    }
+    request.uri().query()?.split('&').find_map(|pair| {
+        let mut pair = pair.splitn(2, '=');
+        let name = pair.next().unwrap_or("");
+        if percent_decode(name)? == param {
+            percent_decode(pair.next().unwrap_or(""))
+        } else {
+            None
+        }
+    })
+}
+
+/// Decode a percent-encoded query string component, with `+` meaning a space. Returns `None` if
+/// it isn't valid UTF-8 once decoded.
+fn percent_decode(encoded: &str) -> Option<String> {
+    let encoded = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = encoded
+            .get(i + 1..i + 3)
+            .filter(|hex| encoded[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (encoded[i], escaped) {
+            (_, Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', None) => decoded.push(b' '),
+            (byte, None) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
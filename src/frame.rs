@@ -0,0 +1,1071 @@
+//! Framing of server sent events. Nothing here depends on Hyper or an async runtime.
+use bytes::{Bytes, BytesMut};
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+
+/// A struct used to build server sent events.
+///
+/// The data, id and event type can each be either borrowed or owned, so both `&str`s and
+/// `String`s can be given to the builder.
+///
+/// # Examples
+/// Build an event with just data:
+/// ```
+/// # use hyper_usse::EventBuilder;
+/// EventBuilder::new("Data").build();
+/// ```
+/// Build an event with an ID:
+/// ```
+/// # use hyper_usse::EventBuilder;
+/// EventBuilder::new("Data").id("Id").build();
+/// ```
+/// Build an event with an event type:
+/// ```
+/// # use hyper_usse::EventBuilder;
+/// EventBuilder::new("Data").event_type("update").build();
+/// ```
+/// Build an event from owned data:
+/// ```
+/// # use hyper_usse::EventBuilder;
+/// let count = 5;
+/// EventBuilder::new(format!("{} users online", count)).event_type("users").build();
+/// ```
+///
+/// Because `EventBuilder` implements `Into<Bytes>` you don't have to call `build` to pass it to
+/// the server.
+///
+/// The fields are public so that a builder can be inspected and changed, but more may be added, so
+/// builders can only be created with [`new`](#method.new) and the other constructors.
+///
+/// # Line breaks in the data
+/// Each line of the data is sent as its own `data:` line, and the client joins them back together
+/// with `\n`. Like clients, the server treats `\r\n`, `\n` and a lone `\r` as line breaks. A
+/// single trailing line break is dropped, as by [`str::lines`]. Empty data
+/// sends no `data:` line at all, so the client doesn't fire an event; but data that is only line
+/// breaks or whitespace sends empty or whitespace `data:` lines, and the client does fire an event:
+/// ```
+/// # use hyper_usse::EventBuilder;
+/// // No event is fired.
+/// assert_eq!(EventBuilder::new("").build(), "\n");
+/// // These all fire an event with empty data.
+/// assert_eq!(EventBuilder::new("\n").build(), "data: \n\n");
+/// assert_eq!(EventBuilder::new("\r\n").build(), "data: \n\n");
+/// // This fires an event with the data "\n".
+/// assert_eq!(EventBuilder::new("\n\n").build(), "data: \ndata: \n\n");
+/// // This fires an event with the data " ".
+/// assert_eq!(EventBuilder::new(" ").build(), "data:  \n\n");
+/// // This fires an event with the data "a", because the trailing line break is dropped.
+/// assert_eq!(EventBuilder::new("a\n").build(), "data: a\n\n");
+/// // This fires an event with the data "a\nb".
+/// assert_eq!(EventBuilder::new("a\rb").build(), "data: a\ndata: b\n\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct EventBuilder<'a> {
+    pub data: Cow<'a, str>,
+    pub id: Option<Cow<'a, str>>,
+    pub event_type: Option<Cow<'a, str>>,
+    /// Extra fields, added with [`field`](#method.field).
+    pub fields: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    /// Comments sent before the event's fields, added with
+    /// [`append_comment_lines`](#method.append_comment_lines). A comment with line breaks is sent
+    /// as several comment lines.
+    pub comments: Vec<Cow<'a, str>>,
+    /// The name of the field the data is sent in, if it isn't `data`. Set with
+    /// [`data_field_name`](#method.data_field_name).
+    pub data_field: Option<Cow<'a, str>>,
+}
+
+/// An [EventBuilder](struct.EventBuilder.html) that owns all of its fields.
+pub type OwnedEventBuilder = EventBuilder<'static>;
+
+pub(crate) const ID_FIELD: &str = "id: ";
+pub(crate) const EVENT_FIELD: &str = "event: ";
+pub(crate) const DATA_FIELD: &str = "data: ";
+
+impl<'a> EventBuilder<'a> {
+    /// Create a new builder with data, no id and no event type.
+    pub fn new(data: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            data: data.into(),
+            id: None,
+            event_type: None,
+            fields: Vec::new(),
+            comments: Vec::new(),
+            data_field: None,
+        }
+    }
+    /// Create a new builder whose data is a value formatted with `Display`, such as a number.
+    /// Multi-line output is sent as several `data:` lines, like any other data.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// assert_eq!(EventBuilder::from_display(42).build(), "data: 42\n\n");
+    /// assert_eq!(
+    ///     EventBuilder::from_display(format_args!("{}\n{}", 1, 2)).build(),
+    ///     "data: 1\ndata: 2\n\n",
+    /// );
+    /// ```
+    pub fn from_display(value: impl Display) -> OwnedEventBuilder {
+        EventBuilder::new(value.to_string())
+    }
+    /// Create a builder for an event carrying binary data. The bytes are base64 encoded into the
+    /// data and the event type is set to `binary`, so that arbitrary bytes can be sent without
+    /// breaking the framing of the stream.
+    ///
+    /// The data can be decoded in the browser with `atob`:
+    /// ```js
+    /// source.addEventListener("binary", event => {
+    ///     const bytes = Uint8Array.from(atob(event.data), c => c.charCodeAt(0));
+    /// });
+    /// ```
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// assert_eq!(
+    ///     EventBuilder::base64(b"\x00\xffhi").build(),
+    ///     "event: binary\ndata: AP9oaQ==\n\n",
+    /// );
+    /// ```
+    pub fn base64(bytes: &[u8]) -> Self {
+        Self::new(base64_encode(bytes)).event_type("binary")
+    }
+    /// Create a builder for an event with only an id. It isn't dispatched, so no handler runs on
+    /// the client, but it sets the id the client sends when it reconnects. This can be sent as a
+    /// checkpoint to stop a client from being replayed events it doesn't need.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let checkpoint = EventBuilder::id_only("5");
+    /// assert_eq!(checkpoint.build(), "id: 5\n\n");
+    /// assert!(!checkpoint.will_dispatch());
+    /// ```
+    pub fn id_only(id: impl Into<Cow<'a, str>>) -> Self {
+        Self::new("").id(id)
+    }
+    /// Create a builder for an event telling the client to switch to another transport, such as
+    /// WebSockets or polling, at `url`. This is for clients behind proxies that break event
+    /// streams. The event type is `fallback` and the data is the URL; nothing else about it is
+    /// special, so clients have to listen for it:
+    /// ```js
+    /// source.addEventListener("fallback", event => {
+    ///     source.close();
+    ///     const url = new URL(event.data, location.href);
+    ///     if (url.protocol === "ws:" || url.protocol === "wss:") {
+    ///         connectWebSocket(url);
+    ///     } else {
+    ///         startPolling(url);
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// assert_eq!(
+    ///     EventBuilder::fallback_hint("wss://example.com/ws").build(),
+    ///     "event: fallback\ndata: wss://example.com/ws\n\n",
+    /// );
+    /// ```
+    pub fn fallback_hint(url: &'a str) -> Self {
+        Self::new(url).event_type("fallback")
+    }
+    /// Create a builder whose data holds some key-value pairs, such as the fields of a structured
+    /// log line.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{EventBuilder, FieldFormat};
+    /// let fields = [("level", "warn"), ("message", "disk \"/\" is\nnearly full")];
+    /// assert_eq!(
+    ///     EventBuilder::from_fields(&fields, FieldFormat::Lines).build(),
+    ///     "data: level=warn\ndata: message=disk \"/\" is\ndata: nearly full\n\n",
+    /// );
+    /// assert_eq!(
+    ///     EventBuilder::from_fields(&fields, FieldFormat::Json).build(),
+    ///     "data: {\"level\":\"warn\",\"message\":\"disk \\\"/\\\" is\\nnearly full\"}\n\n",
+    /// );
+    /// ```
+    pub fn from_fields(fields: &[(&str, &str)], format: FieldFormat) -> Self {
+        let mut data = String::new();
+        match format {
+            FieldFormat::Lines => {
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        data.push('\n');
+                    }
+                    data.push_str(key);
+                    data.push('=');
+                    data.push_str(value);
+                }
+            }
+            FieldFormat::Json => {
+                data.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        data.push(',');
+                    }
+                    push_json_string(&mut data, key);
+                    data.push(':');
+                    push_json_string(&mut data, value);
+                }
+                data.push('}');
+            }
+        }
+        Self::new(data)
+    }
+    /// Set the data. A single leading byte order mark (`U+FEFF`), such as from a file read to
+    /// produce the data, is left out when the event is built.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::default().data("\u{FEFF}Data");
+    /// assert_eq!(event.build(), "data: Data\n\n");
+    /// ```
+    pub fn data(mut self, data: impl Into<Cow<'a, str>>) -> Self {
+        self.data = data.into();
+        self
+    }
+    /// Set the event id. Line breaks can't be sent in an id, so they are left out of it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::new("Data").id("1\ndata: injected");
+    /// assert_eq!(event.build(), "id: 1data: injected\ndata: Data\n\n");
+    /// ```
+    pub fn id(mut self, id: impl Into<Cow<'a, str>>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+    /// Set the event type. Line breaks can't be sent in an event type, so they are left out of it.
+    pub fn event_type(mut self, event_type: impl Into<Cow<'a, str>>) -> Self {
+        self.event_type = Some(event_type.into());
+        self
+    }
+    /// Set the event type to one that has already been validated. Servers that send the same few
+    /// event types over and over can validate each of them once, instead of every time an event
+    /// is built.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{EventBuilder, EventType};
+    /// let update = EventType::new("update").unwrap();
+    /// let event = EventBuilder::new("Data").event_type_validated(update.clone());
+    /// assert_eq!(event.build(), "event: update\ndata: Data\n\n");
+    ///
+    /// assert!(EventType::new("update\ndata: injected").is_err());
+    /// ```
+    pub fn event_type_validated(mut self, event_type: EventType) -> Self {
+        self.event_type = Some(event_type.0);
+        self
+    }
+    /// Set the event type to one of the common event types, which can't be misspelled. See
+    /// [CommonEvent](enum.CommonEvent.html) for the types they are sent as.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{CommonEvent, EventBuilder};
+    /// let event = EventBuilder::new("keep-alive").event(CommonEvent::Ping);
+    /// assert_eq!(event.build(), "event: ping\ndata: keep-alive\n\n");
+    /// ```
+    pub fn event(mut self, event: CommonEvent) -> Self {
+        self.event_type = Some(Cow::Borrowed(event.as_str()));
+        self
+    }
+
+    /// Add a comment, such as a stack trace for debugging, which clients ignore. Every line of
+    /// `text` is sent as its own comment line before the event's fields, so no line can be
+    /// mistaken for a field. Like data, a trailing line break is dropped, and empty text adds no
+    /// comment lines at all.
+    ///
+    /// The text doesn't need validating: clients ignore any line starting with `:`, whatever
+    /// follows it, and every line break in the text starts a new comment line, so no line of the
+    /// text can start a field or end the event.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::new("Failed").append_comment_lines("at main.rs:1\ndata: x\n");
+    /// assert_eq!(event.build(), ": at main.rs:1\n: data: x\ndata: Failed\n\n");
+    ///
+    /// assert_eq!(EventBuilder::new("Data").append_comment_lines("").build(), "data: Data\n\n");
+    /// ```
+    pub fn append_comment_lines(mut self, text: impl Into<Cow<'a, str>>) -> Self {
+        self.comments.push(text.into());
+        self
+    }
+
+    /// Add a custom field, which is sent as `name: value` before the data. Clients ignore fields
+    /// they don't know, so these are only useful to custom clients and monitoring tools.
+    ///
+    /// # Errors
+    /// Fails if the name is empty or contains a colon or line break, or if the value contains a
+    /// line break.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::new("Data").field("trace", "abc123").unwrap();
+    /// assert_eq!(event.build(), "trace: abc123\ndata: Data\n\n");
+    ///
+    /// assert!(EventBuilder::new("Data").field("bad:name", "value").is_err());
+    /// ```
+    pub fn field(
+        mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+    ) -> Result<Self, EventError> {
+        let name = name.into();
+        let value = value.into();
+        check_field(&name, &value)?;
+        self.fields.push((name, value));
+        Ok(self)
+    }
+    /// Send the data in a field other than `data`, for custom SSE-like protocols that use a
+    /// different field name such as `msg`.
+    ///
+    /// This is non-standard: browsers' `EventSource` ignores every field it doesn't know, so it
+    /// won't see any data and never fires the event. Only use this for custom clients.
+    ///
+    /// # Errors
+    /// Fails with [`EventError::InvalidFieldName`](enum.EventError.html) if the name is empty or
+    /// contains a colon or line break.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::new("one\ntwo").data_field_name("msg").unwrap();
+    /// assert_eq!(event.build(), "msg: one\nmsg: two\n\n");
+    /// assert_eq!(event.encoded_len(), event.build().len());
+    /// ```
+    pub fn data_field_name(mut self, name: impl Into<Cow<'a, str>>) -> Result<Self, EventError> {
+        let name = name.into();
+        check_field(&name, "")?;
+        self.data_field = Some(name);
+        Ok(self)
+    }
+    /// Check that the event will be sent exactly as it was given.
+    ///
+    /// Building an event never breaks the framing of the stream, but to keep it that way anything
+    /// that would is left out of the event: line breaks are left out of ids, event types and custom
+    /// field values, so an id of `"1\ndata: x"` is sent as `id: 1data: x`, and colons and line
+    /// breaks are left out of custom field names. [`field`](#method.field) and
+    /// [`data_field_name`](#method.data_field_name) check their arguments, but values set directly
+    /// in [`fields`](#structfield.fields) and [`data_field`](#structfield.data_field) aren't
+    /// checked until this is called.
+    ///
+    /// # Errors
+    /// Fails if the id contains a line break or null character, if the event type contains a line
+    /// break, or if a custom field or the data field name is invalid.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{EventBuilder, EventError};
+    /// assert_eq!(EventBuilder::new("Data").id("1").validate(), Ok(()));
+    /// assert_eq!(EventBuilder::new("Data").id("1\n2").validate(), Err(EventError::InvalidId));
+    ///
+    /// let mut event = EventBuilder::new("Data");
+    /// event.fields.push(("note".into(), "a\ndata: b".into()));
+    /// assert_eq!(event.build(), "note: adata: b\ndata: Data\n\n");
+    /// assert_eq!(event.validate(), Err(EventError::InvalidFieldValue));
+    /// ```
+    pub fn validate(&self) -> Result<(), EventError> {
+        if self.id.as_ref().is_some_and(|id| id.contains(['\n', '\r', '\0'])) {
+            return Err(EventError::InvalidId);
+        }
+        if self.event_type.as_ref().is_some_and(|event_type| event_type.contains(['\n', '\r'])) {
+            return Err(EventError::InvalidEventType);
+        }
+        for (name, value) in &self.fields {
+            check_field(name, value)?;
+        }
+        if let Some(name) = &self.data_field {
+            check_field(name, "")?;
+        }
+        Ok(())
+    }
+    /// Check the event like [`validate`](#method.validate), and also check that the data doesn't
+    /// contain a blank line. Blank lines in the data are sent fine, but they almost always mean
+    /// that an event that was already framed, ending in `\n\n`, was passed as the data of
+    /// another one.
+    ///
+    /// Data that is just a single line break, which sends an event with empty data, is allowed.
+    ///
+    /// # Errors
+    /// Fails like `validate`, or with
+    /// [`EventError::BlankLineInData`](enum.EventError.html#variant.BlankLineInData) if the data
+    /// contains a blank line.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{EventBuilder, EventError};
+    /// let framed = EventBuilder::new("Data").build();
+    /// assert_eq!(EventBuilder::new(framed).validate_strict(), Err(EventError::BlankLineInData));
+    /// let gap = EventBuilder::new("one\n\ntwo");
+    /// assert_eq!(gap.validate_strict(), Err(EventError::BlankLineInData));
+    ///
+    /// assert_eq!(EventBuilder::new("one\ntwo").validate_strict(), Ok(()));
+    /// assert_eq!(EventBuilder::new("\n").validate_strict(), Ok(()));
+    /// ```
+    pub fn validate_strict(&self) -> Result<(), EventError> {
+        self.validate()?;
+        if lines(self.sent_data()).count() > 1 && lines(self.sent_data()).any(str::is_empty) {
+            return Err(EventError::BlankLineInData);
+        }
+        Ok(())
+    }
+    /// Set the event id to the empty string. This sends an empty `id` field, which resets the id
+    /// that the client will send in `Last-Event-ID` when reconnecting.
+    ///
+    /// This is different from [`clear_id`](#method.clear_id), which sends no `id` field at all and
+    /// so leaves the client's last event id as it was.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// assert_eq!(EventBuilder::new("Data").reset_id().build(), "id: \ndata: Data\n\n");
+    /// assert_eq!(EventBuilder::new("Data").id("1").clear_id().build(), "data: Data\n\n");
+    /// ```
+    pub fn reset_id(self) -> Self {
+        self.id("")
+    }
+    /// Clear the event id, so that no `id` field is sent.
+    pub fn clear_id(mut self) -> Self {
+        self.id = None;
+        self
+    }
+    /// Clear the event type.
+    pub fn clear_type(mut self) -> Self {
+        self.event_type = None;
+        self
+    }
+    /// Convert this builder into one that owns all of its fields, copying any borrowed ones.
+    pub fn into_owned(self) -> OwnedEventBuilder {
+        EventBuilder {
+            data: Cow::Owned(self.data.into_owned()),
+            id: self.id.map(|id| Cow::Owned(id.into_owned())),
+            event_type: self.event_type.map(|event_type| Cow::Owned(event_type.into_owned())),
+            fields: self.fields
+                .into_iter()
+                .map(|(name, value)| (Cow::Owned(name.into_owned()), Cow::Owned(value.into_owned())))
+                .collect(),
+            comments: self.comments
+                .into_iter()
+                .map(|comment| Cow::Owned(comment.into_owned()))
+                .collect(),
+            data_field: self.data_field.map(|name| Cow::Owned(name.into_owned())),
+        }
+    }
+    /// Check whether the built event will make clients fire an event. Clients only fire events
+    /// that have at least one `data` line, even an empty one, so events with empty data only
+    /// update the client's last event id, and a `data` of `"\n"` fires an event with empty data.
+    /// Events whose data is sent in another field with
+    /// [`data_field_name`](#method.data_field_name) never fire an event for browsers, unless that
+    /// field is `data` itself.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// assert!(EventBuilder::new("Data").will_dispatch());
+    /// assert!(EventBuilder::new("\n").will_dispatch());
+    /// assert!(!EventBuilder::new("").id("1").will_dispatch());
+    /// assert!(!EventBuilder::new("").append_comment_lines("Just a comment").will_dispatch());
+    /// assert!(!EventBuilder::new("Data").data_field_name("msg").unwrap().will_dispatch());
+    /// assert!(EventBuilder::new("Data").data_field_name("data").unwrap().will_dispatch());
+    /// ```
+    pub fn will_dispatch(&self) -> bool {
+        let data_field = self.data_field.as_deref().map_or(true, |name| name == "data");
+        let data_lines = data_field && lines(self.sent_data()).next().is_some();
+        data_lines || self.fields.iter().any(|(name, _)| name == "data")
+    }
+    /// Get the exact length in bytes of the built event, without building it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let event = EventBuilder::new("Multi-line\r\ndata\n").id("1").event_type("update");
+    /// assert_eq!(event.encoded_len(), event.build().len());
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        self.id.as_ref().map(|id| 5 + single_line_len(id)).unwrap_or(0) +
+        self.event_type.as_ref().map(|event| 8 + single_line_len(event)).unwrap_or(0) +
+        self.fields
+            .iter()
+            .map(|(name, value)| field_name_len(name) + 3 + single_line_len(value))
+            .sum::<usize>() +
+        self.comments.iter()
+            .flat_map(|comment| lines(comment))
+            .map(|line| 3 + line.len())
+            .sum::<usize>() +
+        lines(self.sent_data()).map(|line| self.data_prefix_len() + line.len() + 1).sum::<usize>() +
+        1
+    }
+    /// Build the event.
+    pub fn build(&self) -> String {
+        let mut event = String::with_capacity(self.capacity());
+        self.write_to(&mut event);
+        event
+    }
+    /// Build the event onto the end of a buffer, without going through a `String`.
+    ///
+    /// This lets servers that manage their own buffers reuse one allocation for many events,
+    /// splitting each event off with [`BytesMut::split`](bytes::BytesMut::split).
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::EventBuilder;
+    /// let mut buf = bytes::BytesMut::new();
+    /// EventBuilder::new("first").build_into(&mut buf);
+    /// let first = buf.split().freeze();
+    /// EventBuilder::new("second").id("2").build_into(&mut buf);
+    ///
+    /// assert_eq!(first, "data: first\n\n");
+    /// assert_eq!(buf, "id: 2\ndata: second\n\n");
+    /// ```
+    pub fn build_into(&self, buf: &mut BytesMut) {
+        buf.reserve(self.capacity());
+        self.write_to(buf);
+    }
+    /// The capacity to allocate for the built event: exact for simple events, and otherwise
+    /// calculated with `encoded_len`.
+    fn capacity(&self) -> usize {
+        if self.is_simple() {
+            DATA_FIELD.len() + self.sent_data().len() + 2
+        } else {
+            self.encoded_len()
+        }
+    }
+    /// The data that is sent, without a leading byte order mark. Clients strip a byte order mark
+    /// from the start of the stream, so one in the data would either be stripped by the client
+    /// or shown as part of the data.
+    fn sent_data(&self) -> &str {
+        self.data.strip_prefix('\u{FEFF}').unwrap_or(&self.data)
+    }
+    /// The length of the field name and separator at the start of each data line.
+    fn data_prefix_len(&self) -> usize {
+        self.data_field.as_ref().map_or(DATA_FIELD.len(), |name| field_name_len(name) + 2)
+    }
+    /// Whether the event is a single line of data with no other fields, like most events are.
+    fn is_simple(&self) -> bool {
+        self.id.is_none()
+            && self.event_type.is_none()
+            && self.fields.is_empty()
+            && self.comments.is_empty()
+            && self.data_field.is_none()
+            && !self.sent_data().is_empty()
+            && !self.sent_data().contains(['\n', '\r'])
+    }
+    /// Write the built event to the end of a buffer.
+    pub(crate) fn write_to(&self, event: &mut impl EventBuffer) {
+        if self.is_simple() {
+            event.push_str(DATA_FIELD);
+            event.push_str(self.sent_data());
+            event.push_str("\n\n");
+            return;
+        }
+
+        for line in self.comments.iter().flat_map(|comment| lines(comment)) {
+            event.push_str(": ");
+            event.push_str(line);
+            event.push_str("\n");
+        }
+        if let Some(id) = &self.id {
+            event.push_str(ID_FIELD);
+            push_single_line(event, id);
+            event.push_str("\n");
+        }
+        if let Some(event_type) = &self.event_type {
+            event.push_str(EVENT_FIELD);
+            push_single_line(event, event_type);
+            event.push_str("\n");
+        }
+        for (name, value) in &self.fields {
+            push_field_name(event, name);
+            event.push_str(": ");
+            push_single_line(event, value);
+            event.push_str("\n");
+        }
+        for line in lines(self.sent_data()) {
+            match &self.data_field {
+                Some(name) => {
+                    push_field_name(event, name);
+                    event.push_str(": ");
+                }
+                None => event.push_str(DATA_FIELD),
+            }
+            event.push_str(line);
+            event.push_str("\n");
+        }
+        event.push_str("\n");
+    }
+}
+
+/// A buffer that events can be written to.
+pub(crate) trait EventBuffer {
+    fn push_str(&mut self, s: &str);
+}
+
+/// Check that a custom field can be sent without breaking the framing of the event.
+pub(crate) fn check_field(name: &str, value: &str) -> Result<(), EventError> {
+    if name.is_empty() || name.contains([':', '\n', '\r']) {
+        return Err(EventError::InvalidFieldName);
+    }
+    if value.contains(['\n', '\r']) {
+        return Err(EventError::InvalidFieldValue);
+    }
+    Ok(())
+}
+
+/// Split text into lines at `\r\n`, `\n` and lone `\r`, which clients all treat as line breaks.
+/// Like [`str::lines`], a single trailing line break doesn't start another line.
+pub(crate) fn lines(text: &str) -> Lines<'_> {
+    Lines(text)
+}
+
+/// The iterator returned by [`lines`].
+pub(crate) struct Lines<'a>(&'a str);
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let (line, rest) = match self.0.find(['\r', '\n']) {
+            Some(i) => {
+                let break_len = if self.0[i..].starts_with("\r\n") { 2 } else { 1 };
+                (&self.0[..i], &self.0[i + break_len..])
+            }
+            None => (self.0, ""),
+        };
+        self.0 = rest;
+        Some(line)
+    }
+}
+
+/// Push a field value to a buffer, leaving out any line breaks so it can't end the field early.
+pub(crate) fn push_single_line(event: &mut impl EventBuffer, value: &str) {
+    for part in value.split(['\r', '\n']) {
+        event.push_str(part);
+    }
+}
+
+/// The length of a field value once its line breaks are left out.
+fn single_line_len(value: &str) -> usize {
+    value.len() - value.bytes().filter(|&b| b == b'\r' || b == b'\n').count()
+}
+
+/// Push a custom field name to a buffer, leaving out any colons and line breaks so it can't end
+/// the name or the field early.
+fn push_field_name(event: &mut impl EventBuffer, name: &str) {
+    for part in name.split([':', '\r', '\n']) {
+        event.push_str(part);
+    }
+}
+
+/// The length of a custom field name once its colons and line breaks are left out.
+fn field_name_len(name: &str) -> usize {
+    name.len() - name.bytes().filter(|&b| b == b':' || b == b'\r' || b == b'\n').count()
+}
+
+impl EventBuffer for String {
+    fn push_str(&mut self, s: &str) {
+        String::push_str(self, s);
+    }
+}
+
+impl EventBuffer for BytesMut {
+    fn push_str(&mut self, s: &str) {
+        self.extend_from_slice(s.as_bytes());
+    }
+}
+
+impl<'a> From<&'a str> for EventBuilder<'a> {
+    fn from(data: &'a str) -> Self {
+        Self::new(data)
+    }
+}
+
+impl From<String> for EventBuilder<'static> {
+    fn from(data: String) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a> Display for EventBuilder<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.build())
+    }
+}
+
+impl<'a> From<EventBuilder<'a>> for Bytes {
+    fn from(event: EventBuilder<'a>) -> Self {
+        event.build().into()
+    }
+}
+
+/// An event that has been built once, so that it can be broadcast many times without being built
+/// again, such as a periodic tick whose content never changes. The builder it was built from is
+/// kept alongside it for debugging.
+///
+/// Sending a cached event only clones its `Bytes`, which doesn't copy the event. It can be sent
+/// with [`Server::broadcast_cached`](struct.Server.html#method.broadcast_cached), or anywhere else
+/// that takes `Into<Bytes>`.
+///
+/// # Examples
+/// ```
+/// # futures::executor::block_on(async {
+/// # use hyper_usse::{CachedEvent, EventBuilder};
+/// let tick = CachedEvent::new(EventBuilder::new("tick").event_type("tick"));
+/// assert_eq!(tick.bytes(), "event: tick\ndata: tick\n\n");
+///
+/// let mut server = hyper_usse::Server::new();
+/// server.broadcast_cached(&tick).await;
+/// server.send_to_clients(&tick).await;
+/// # });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedEvent {
+    event: OwnedEventBuilder,
+    bytes: Bytes,
+}
+
+impl CachedEvent {
+    /// Build an event to cache it.
+    pub fn new(event: OwnedEventBuilder) -> Self {
+        let bytes = event.build().into();
+        Self { event, bytes }
+    }
+    /// Get the builder the event was built from.
+    pub fn event(&self) -> &OwnedEventBuilder {
+        &self.event
+    }
+    /// Get the built event.
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl From<OwnedEventBuilder> for CachedEvent {
+    fn from(event: OwnedEventBuilder) -> Self {
+        Self::new(event)
+    }
+}
+
+impl From<&CachedEvent> for Bytes {
+    fn from(event: &CachedEvent) -> Self {
+        event.bytes.slice(..)
+    }
+}
+
+/// A type that can be turned into an event, choosing its own event type, id and data format.
+///
+/// Every `Display` type is turned into an event with just its formatted output as the data, like
+/// [`EventBuilder::from_display`](struct.EventBuilder.html#method.from_display). Because of that,
+/// a type can only implement this trait itself if it doesn't implement `Display`.
+///
+/// # Examples
+/// ```
+/// # use hyper_usse::{EventBuilder, OwnedEventBuilder, ToEvent};
+/// struct Score {
+///     team: &'static str,
+///     points: u32,
+/// }
+///
+/// impl ToEvent for Score {
+///     fn to_event(&self) -> OwnedEventBuilder {
+///         EventBuilder::new(format!("{} {}", self.team, self.points)).event_type("score")
+///     }
+/// }
+///
+/// let score = Score { team: "home", points: 3 };
+/// assert_eq!(score.to_event().build(), "event: score\ndata: home 3\n\n");
+/// assert_eq!(42.to_event().build(), "data: 42\n\n");
+/// ```
+pub trait ToEvent {
+    /// Build the event for this value.
+    fn to_event(&self) -> OwnedEventBuilder;
+}
+
+impl<T: Display + ?Sized> ToEvent for T {
+    fn to_event(&self) -> OwnedEventBuilder {
+        EventBuilder::from_display(self)
+    }
+}
+
+/// An event parsed from an event stream, such as one received from an upstream server by a relay.
+///
+/// Fields are kept in the order they were received, so two parsed events that mean the same
+/// thing can differ; compare them with [`semantically_eq`](#method.semantically_eq).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ParsedEvent {
+    /// The value of the last `id` field, if there was one.
+    pub id: Option<String>,
+    /// The value of the last `event` field, if there was one.
+    pub event_type: Option<String>,
+    /// The values of the `data` fields, joined with `\n`.
+    pub data: String,
+    /// Every field, including `id`, `event` and `data`, in the order they were received.
+    pub fields: Vec<(String, String)>,
+    /// The comment lines, without the leading colon.
+    pub comments: Vec<String>,
+}
+
+impl ParsedEvent {
+    /// Parse every complete event in `stream`. Anything after the last blank line is ignored, as
+    /// the event it belongs to hasn't ended yet.
+    ///
+    /// Unlike clients, this also returns events without data, so that every event that was sent
+    /// can be inspected.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::{EventBuilder, ParsedEvent};
+    /// let events = ParsedEvent::parse(&EventBuilder::new("a\nb").id("1").build());
+    /// assert_eq!(events.len(), 1);
+    /// assert_eq!(events[0].id.as_deref(), Some("1"));
+    /// assert_eq!(events[0].data, "a\nb");
+    ///
+    /// // The second event hasn't been ended by a blank line.
+    /// assert_eq!(ParsedEvent::parse("data: first\n\ndata: second\n").len(), 1);
+    /// ```
+    pub fn parse(stream: &str) -> Vec<ParsedEvent> {
+        let mut events = Vec::new();
+        let mut event = ParsedEvent::default();
+        let mut has_lines = false;
+        let mut data_lines = 0;
+        for line in lines(stream) {
+            if line.is_empty() {
+                if has_lines {
+                    events.push(std::mem::take(&mut event));
+                }
+                has_lines = false;
+                data_lines = 0;
+                continue;
+            }
+            has_lines = true;
+            if let Some(comment) = line.strip_prefix(':') {
+                event.comments.push(comment.to_owned());
+                continue;
+            }
+            let (name, value) = match line.find(':') {
+                Some(i) => {
+                    let value = &line[i + 1..];
+                    (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+                }
+                None => (line, ""),
+            };
+            match name {
+                "id" => event.id = Some(value.to_owned()),
+                "event" => event.event_type = Some(value.to_owned()),
+                "data" => {
+                    if data_lines > 0 {
+                        event.data.push('\n');
+                    }
+                    event.data.push_str(value);
+                    data_lines += 1;
+                }
+                _ => {}
+            }
+            event.fields.push((name.to_owned(), value.to_owned()));
+        }
+        events
+    }
+
+    /// Check whether two events mean the same thing to a client: whether they have the same id,
+    /// event type and data, regardless of the order of their fields or their comments.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::ParsedEvent;
+    /// let a = &ParsedEvent::parse("id: 1\nevent: update\ndata: x\n\n")[0];
+    /// let b = &ParsedEvent::parse(": relayed\ndata: x\nevent: update\nid: 1\n\n")[0];
+    /// assert_ne!(a, b);
+    /// assert!(a.semantically_eq(b));
+    /// ```
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.event_type == other.event_type && self.data == other.data
+    }
+}
+
+/// How [`EventBuilder::from_fields`](struct.EventBuilder.html#method.from_fields) formats
+/// key-value pairs into an event's data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldFormat {
+    /// One `key=value` pair per line. Values containing line breaks continue over several lines.
+    Lines,
+    /// A single JSON object with string values.
+    Json,
+}
+
+/// Push a string to `buf` as a quoted JSON string.
+fn push_json_string(buf: &mut String, s: &str) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+/// An error building an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EventError {
+    /// A field name was empty or contained a colon or line break.
+    InvalidFieldName,
+    /// A field value contained a line break.
+    InvalidFieldValue,
+    /// The id contained a line break or a null character, which makes clients ignore it.
+    InvalidId,
+    /// The event type contained a line break.
+    InvalidEventType,
+    /// The built event was longer than the server's maximum event size.
+    TooLarge,
+    /// The data contained a blank line, which usually means it was an already framed event. Only
+    /// returned by strict validation.
+    BlankLineInData,
+    /// Pre-framed events didn't end with a blank line, so the last of them wouldn't be dispatched
+    /// until more text arrived.
+    Unterminated,
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidFieldName => "field name is empty or contains a colon or line break",
+            Self::InvalidFieldValue => "field value contains a line break",
+            Self::InvalidId => "id contains a line break or null character",
+            Self::InvalidEventType => "event type contains a line break",
+            Self::TooLarge => "event is larger than the maximum event size",
+            Self::BlankLineInData => "data contains a blank line",
+            Self::Unterminated => "framed events don't end with a blank line",
+        })
+    }
+}
+
+impl std::error::Error for EventError {}
+
+/// An event type that has been checked to be valid, for use with
+/// [`EventBuilder::event_type_validated`](struct.EventBuilder.html#method.event_type_validated).
+///
+/// Cloning an event type created from a `&'static str` doesn't allocate.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EventType(Cow<'static, str>);
+
+impl EventType {
+    /// Validate an event type.
+    ///
+    /// # Errors
+    /// Fails with [`EventError::InvalidEventType`](enum.EventError.html) if the event type
+    /// contains a line break.
+    pub fn new(event_type: impl Into<Cow<'static, str>>) -> Result<Self, EventError> {
+        let event_type = event_type.into();
+        if event_type.contains(['\n', '\r']) {
+            return Err(EventError::InvalidEventType);
+        }
+        Ok(Self(event_type))
+    }
+    /// Get the event type as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for EventType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An event type that is common in SSE applications, for use with
+/// [`EventBuilder::event`](struct.EventBuilder.html#method.event). Other event types can be set as
+/// strings with [`event_type`](struct.EventBuilder.html#method.event_type).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CommonEvent {
+    /// Sent as `message`, the type of events that don't set one. Browsers pass these events to
+    /// `onmessage`.
+    Message,
+    /// Sent as `open`. Browsers also fire an `open` event themselves when the connection opens, so
+    /// `onopen` handlers see both.
+    Open,
+    /// Sent as `error`. Browsers also fire an `error` event themselves when the connection fails,
+    /// so handlers have to tell the two apart, such as by whether the event has data.
+    Error,
+    /// Sent as `ping`, for keep-alives that clients can see, unlike comments. Like any event, it
+    /// needs data to be dispatched.
+    Ping,
+}
+
+impl CommonEvent {
+    /// Get the event type the event is sent as.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Open => "open",
+            Self::Error => "error",
+            Self::Ping => "ping",
+        }
+    }
+}
+
+impl Display for CommonEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<CommonEvent> for EventType {
+    fn from(event: CommonEvent) -> Self {
+        Self(Cow::Borrowed(event.as_str()))
+    }
+}
+
+/// Encode bytes as standard, padded base64.
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | chunk.get(1).map_or(0, |&b| b as u32) << 8
+            | chunk.get(2).map_or(0, |&b| b as u32);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Get the id of a complete event, if it has one. If the event sets its id several times, the last
+/// one is used, as it is by clients.
+pub(crate) fn event_id(event: &[u8]) -> Option<&str> {
+    let event = std::str::from_utf8(event.strip_suffix(b"\n\n")?).ok()?;
+    lines(event)
+        .filter_map(|line| line.strip_prefix("id"))
+        .filter_map(|value| match value.strip_prefix(':') {
+            Some(value) => Some(value.strip_prefix(' ').unwrap_or(value)),
+            None if value.is_empty() => Some(""),
+            None => None,
+        })
+        .last()
+}
@@ -0,0 +1,173 @@
+//! Running a server in a background task.
+use hyper::body::{Bytes, Sender};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::server::ClientId;
+
+/// A handle to a [Server](struct.Server.html) running in a background task, created by
+/// [`Server::spawn`](struct.Server.html#method.spawn).
+///
+/// Every method sends a command to the task and waits for it to be carried out. The task carries
+/// out one command at a time, in the order they are received; so commands sent through the same
+/// handle are carried out in the order they were sent, and a command is finished with before the
+/// next one starts. There is no ordering between commands sent concurrently through different
+/// handles.
+///
+/// All methods panic if the server's task has panicked.
+#[derive(Debug)]
+pub struct ServerHandle<S = Sender> {
+    pub(crate) commands: mpsc::UnboundedSender<Command<S>>,
+}
+
+impl<S> Clone for ServerHandle<S> {
+    fn clone(&self) -> Self {
+        ServerHandle { commands: self.commands.clone() }
+    }
+}
+
+/// A command sent to a spawned server, with a channel to send its result back through.
+#[derive(Debug)]
+pub(crate) enum Command<S> {
+    AddClient(S, oneshot::Sender<ClientId>),
+    Broadcast(Bytes, oneshot::Sender<usize>),
+    Connections(oneshot::Sender<usize>),
+    DisconnectAll(oneshot::Sender<()>),
+    /// Sent by a dropped [ClientGuard](struct.ClientGuard.html), which can't wait for a reply.
+    RemoveClient(ClientId),
+}
+
+impl<S> ServerHandle<S> {
+    /// Add a client to the server. See [`Server::add_client`](struct.Server.html#method.add_client).
+    pub async fn add_client(&self, client: S) -> ClientId {
+        self.request(|reply| Command::AddClient(client, reply)).await
+    }
+
+    /// Add a client to the server that is removed again when the returned guard is dropped. See
+    /// [ClientGuard](struct.ClientGuard.html).
+    pub async fn add_guarded_client(&self, client: S) -> ClientGuard<S> {
+        ClientGuard {
+            id: self.add_client(client).await,
+            commands: self.commands.clone(),
+        }
+    }
+
+    /// Send some text to the clients. See
+    /// [`Server::send_to_clients`](struct.Server.html#method.send_to_clients).
+    pub async fn broadcast<B: Into<Bytes>>(&self, text: B) -> usize {
+        let bytes = text.into();
+        self.request(|reply| Command::Broadcast(bytes, reply)).await
+    }
+
+    /// Count the number of currently held connections. See
+    /// [`Server::connections`](struct.Server.html#method.connections).
+    pub async fn connections(&self) -> usize {
+        self.request(Command::Connections).await
+    }
+
+    /// Disconnect all clients that are currently connected to the server.
+    pub async fn disconnect_all(&self) {
+        self.request(Command::DisconnectAll).await
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command<S>) -> T {
+        let (reply, response) = oneshot::channel();
+        if self.commands.send(command(reply)).is_err() {
+            panic!("server task panicked");
+        }
+        response.await.expect("server task panicked")
+    }
+}
+
+/// A client of a spawned server that is removed from the server when this guard is dropped, created
+/// by [`ServerHandle::add_guarded_client`](struct.ServerHandle.html#method.add_guarded_client).
+///
+/// This ties a client to a scope such as a request handler's future, so that if the future is
+/// cancelled the client is removed straight away instead of lingering until the next broadcast
+/// notices its connection has closed. The client's stream ends cleanly when it is removed.
+///
+/// The guard doesn't stop the server from detecting closed connections as usual: if the
+/// connection closes first the client is still pruned by the next broadcast, and dropping the
+/// guard afterwards does nothing. Note that a handler usually returns the response body before
+/// the client is finished with, so the guard has to be stored somewhere that lives as long as the
+/// connection rather than dropped when the handler returns.
+///
+/// # Examples
+/// ```
+/// # #[tokio::main]
+/// # async fn main() {
+/// let server = hyper_usse::Server::new().spawn();
+/// let (client, _body) = hyper::Body::channel();
+/// let guard = server.add_guarded_client(client).await;
+/// assert_eq!(server.connections().await, 1);
+///
+/// drop(guard);
+/// assert_eq!(server.connections().await, 0);
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use = "the client is removed as soon as the guard is dropped"]
+pub struct ClientGuard<S = Sender> {
+    id: ClientId,
+    commands: mpsc::UnboundedSender<Command<S>>,
+}
+
+impl<S> ClientGuard<S> {
+    /// Get the id of the guarded client.
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+}
+
+impl<S> Drop for ClientGuard<S> {
+    fn drop(&mut self) {
+        // If the server's task has stopped the client is already gone.
+        let _ = self.commands.send(Command::RemoveClient(self.id));
+    }
+}
+
+/// A handle to the task sending heartbeats to a shared server, created by
+/// [`Server::spawn_heartbeat`](struct.Server.html#method.spawn_heartbeat).
+///
+/// The task only holds a weak reference to the server, so it never keeps the server alive. It
+/// stops when the first of these happens:
+/// - [`stop`](#method.stop) is called, which waits for it to finish.
+/// - The handle is dropped. The task then stops at its next heartbeat at the latest, without
+///   being waited for.
+/// - Every other reference to the server has been dropped. The task notices at its next heartbeat.
+///
+/// # Examples
+/// ```
+/// # #[tokio::main]
+/// # async fn main() {
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use tokio::sync::Mutex;
+/// use hyper_usse::Server;
+///
+/// let server = Arc::new(Mutex::new(Server::new()));
+/// let heartbeat = Server::spawn_heartbeat(&server, Duration::from_secs(15));
+///
+/// // The task is finished with by the time `stop` returns.
+/// heartbeat.stop().await;
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use = "the heartbeat task stops as soon as its handle is dropped"]
+pub struct HeartbeatHandle {
+    pub(crate) stop: oneshot::Sender<()>,
+    pub(crate) task: JoinHandle<()>,
+}
+
+impl HeartbeatHandle {
+    /// Stop sending heartbeats, waiting for the task to finish. If a heartbeat is being sent it is
+    /// finished first.
+    ///
+    /// # Panics
+    /// Panics if the task panicked.
+    pub async fn stop(self) {
+        // If the task has already stopped there is nothing to tell it.
+        let _ = self.stop.send(());
+        self.task.await.expect("heartbeat task panicked");
+    }
+}
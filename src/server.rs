@@ -0,0 +1,3824 @@
+//! The SSE server.
+use bytes::BytesMut;
+use futures::future;
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+use hyper::body::{Body, Bytes, Sender};
+use hyper::{HeaderMap, Version};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+use std::panic::{self, UnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::frame::{event_id, lines, push_single_line, DATA_FIELD, EVENT_FIELD, ID_FIELD};
+use crate::frame::{EventBuilder, EventError, OwnedEventBuilder, ToEvent};
+use crate::handle::{Command, HeartbeatHandle, ServerHandle};
+use crate::sink::{readiness, send_with_retries, try_send, EventSink, Readiness, RetryingSend, SinkError};
+use crate::CachedEvent;
+
+/// The key under which
+/// [`Server::broadcast_all_topics`](struct.Server.html#method.broadcast_all_topics) reports the
+/// global clients, those added with
+/// [`add_global_client`](struct.Server.html#method.add_global_client). Topics shouldn't be given
+/// this name, or their clients will be counted together with the global clients.
+pub const GLOBAL_CLIENTS_KEY: &str = "*";
+
+/// A callback run by the server when its clients change.
+type Callback = Box<dyn Fn() + Send>;
+
+/// A callback run by the server when something happens to one of its clients.
+type ClientCallback = Box<dyn Fn(ClientId) + Send>;
+
+/// An identifier for a client of a [Server](struct.Server.html), returned by `add_client`.
+///
+/// Ids are unique within a server and are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(u64);
+
+impl Display for ClientId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl From<ClientId> for u64 {
+    fn from(id: ClientId) -> Self {
+        id.0
+    }
+}
+
+impl From<u64> for ClientId {
+    /// Recreate a client id from its number, such as one stored in a
+    /// [ServerState](struct.ServerState.html).
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// Information about a client of a [Server](struct.Server.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientMeta {
+    /// The client's id.
+    pub id: ClientId,
+    /// When the client was added to the server.
+    pub connected_at: Instant,
+    /// The label the client was added with by
+    /// [`add_labeled_client`](struct.Server.html#method.add_labeled_client), such as the name of
+    /// the user it belongs to.
+    pub label: Option<String>,
+    /// The key the client was added with by
+    /// [`register_resumable`](struct.Server.html#method.register_resumable).
+    pub resume_key: Option<String>,
+    /// When the client was last successfully sent something, if it has been.
+    pub last_sent: Option<Instant>,
+    /// The topic the client was added to by
+    /// [`add_client_to_topic`](struct.Server.html#method.add_client_to_topic).
+    pub topic: Option<String>,
+    /// Whether the client was added by
+    /// [`add_global_client`](struct.Server.html#method.add_global_client) to receive the events
+    /// sent to every topic.
+    pub global: bool,
+    /// The user the client was added for by
+    /// [`add_client_for_user`](struct.Server.html#method.add_client_for_user).
+    pub user_id: Option<String>,
+    /// How long the most recent broadcast to the client took to be accepted by its connection,
+    /// including any retries. Long sends mean the client isn't keeping up with the events.
+    pub last_send_latency: Option<Duration>,
+    /// The HTTP version of the client's connection, if it was added with
+    /// [`add_client_with_version`](struct.Server.html#method.add_client_with_version).
+    pub version: Option<Version>,
+    /// The bucket the client was put in by
+    /// [`add_client_with_bucket_key`](struct.Server.html#method.add_client_with_bucket_key).
+    pub bucket: Option<u32>,
+    /// The key the client was added with by
+    /// [`add_client_dedup`](struct.Server.html#method.add_client_dedup).
+    pub dedup_key: Option<String>,
+}
+
+/// The attributes to add a client to a [Server](struct.Server.html) with, using
+/// [`Server::add_client_with`](struct.Server.html#method.add_client_with). Each of the server's
+/// other `add_client` methods sets one of these, while this can set any combination of them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientOptions {
+    last_event_id: Option<String>,
+    resume_key: Option<String>,
+    label: Option<String>,
+    topic: Option<String>,
+    global: bool,
+    user_id: Option<String>,
+    version: Option<Version>,
+    bucket_key: Option<Vec<u8>>,
+    dedup_key: Option<String>,
+}
+
+impl ClientOptions {
+    /// Create options that add a plain client, like
+    /// [`Server::add_client`](struct.Server.html#method.add_client).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replay the events after the one with this id to the client, like
+    /// [`Server::add_client_with_last_id`](struct.Server.html#method.add_client_with_last_id).
+    /// This takes priority over the last event id remembered for the client's resume key.
+    pub fn with_last_event_id(mut self, last_event_id: impl Into<String>) -> Self {
+        self.last_event_id = Some(last_event_id.into());
+        self
+    }
+
+    /// Make the client resumable under `key`, like
+    /// [`Server::register_resumable`](struct.Server.html#method.register_resumable).
+    pub fn with_resume_key(mut self, key: impl Into<String>) -> Self {
+        self.resume_key = Some(key.into());
+        self
+    }
+
+    /// Label the client, like
+    /// [`Server::add_labeled_client`](struct.Server.html#method.add_labeled_client).
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Subscribe the client to a topic, like
+    /// [`Server::add_client_to_topic`](struct.Server.html#method.add_client_to_topic).
+    pub fn with_topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Send the client the events sent to every topic, like
+    /// [`Server::add_global_client`](struct.Server.html#method.add_global_client).
+    pub fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
+    /// Add the client for a user, like
+    /// [`Server::add_client_for_user`](struct.Server.html#method.add_client_for_user).
+    pub fn with_user(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Record the HTTP version of the client's connection, like
+    /// [`Server::add_client_with_version`](struct.Server.html#method.add_client_with_version).
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Put the client in the bucket picked by hashing `key`, like
+    /// [`Server::add_client_with_bucket_key`](struct.Server.html#method.add_client_with_bucket_key).
+    pub fn with_bucket_key(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.bucket_key = Some(key.as_ref().to_vec());
+        self
+    }
+
+    /// Replace any client already connected with the same key, like
+    /// [`Server::add_client_dedup`](struct.Server.html#method.add_client_dedup).
+    pub fn with_dedup_key(mut self, dedup_key: impl Into<String>) -> Self {
+        self.dedup_key = Some(dedup_key.into());
+        self
+    }
+}
+
+/// Hash data with 64-bit FNV-1a, which is simple and never changes.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A client connected to a server.
+#[derive(Debug)]
+struct Client<S> {
+    meta: ClientMeta,
+    sender: S,
+    /// Events with ids that were sent to this client but haven't been acknowledged, oldest first.
+    /// Only filled if the server tracks acknowledgements.
+    pending: VecDeque<(String, Bytes)>,
+    /// The bytes last sent to this client and whether sending them succeeded, until the server
+    /// has recorded the outcome. This is only set during a broadcast, or after a broadcast was
+    /// cancelled and until the next one.
+    outcome: Option<(Bytes, bool)>,
+    /// The reconnection time in milliseconds to send before the next event broadcast to this
+    /// client, set by `set_default_retry_for`.
+    retry: Option<u64>,
+    /// The latest event the client wasn't ready for, held to be sent with the next one under
+    /// `BackpressureStrategy::DropOldest`.
+    held: Option<Bytes>,
+}
+
+impl<S> Client<S> {
+    /// Put the client's pending reconnection time, if any, in front of the bytes it is about to be
+    /// sent.
+    fn with_retry(&mut self, bytes: Bytes) -> Bytes {
+        match self.retry.take() {
+            Some(retry) => {
+                let mut buf = BytesMut::from(format!("retry: {}\n\n", retry).as_str());
+                buf.extend_from_slice(&bytes);
+                buf.freeze()
+            }
+            None => bytes,
+        }
+    }
+    /// Record that the client was sent some bytes at `now`, remembering the id of the event for
+    /// acknowledgements and resuming.
+    fn sent(
+        &mut self,
+        bytes: &Bytes,
+        now: Instant,
+        track_acks: bool,
+        resume_points: &mut HashMap<String, String>,
+    ) {
+        self.meta.last_sent = Some(now);
+        if let Some(id) = event_id(bytes) {
+            if track_acks {
+                self.pending.push_back((id.to_owned(), bytes.slice(..)));
+            }
+            if let Some(key) = &self.meta.resume_key {
+                resume_points.insert(key.clone(), id.to_owned());
+            }
+        }
+    }
+}
+
+impl<S: EventSink> Client<S> {
+    /// Send the client `bytes`, if there are any, treating it according to `backpressure` if it
+    /// isn't ready, and record the outcome.
+    async fn send(&mut self, bytes: Option<Bytes>, retries: u32, backpressure: BackpressureStrategy) {
+        if let Some(bytes) = bytes {
+            let mut send = ClientSend::new(bytes, retries, backpressure);
+            future::poll_fn(|cx| send.poll(self, cx)).await;
+        }
+    }
+}
+
+/// Sending bytes to a single client, treating it according to a backpressure strategy if it isn't
+/// ready, as a state machine that is polled with the client. The outcome is recorded in the client
+/// once the send has finished. Both `Client::send` and
+/// [SendProgress](struct.SendProgress.html) are built on this.
+#[derive(Debug)]
+struct ClientSend {
+    /// The bytes to send, until the client has been checked.
+    bytes: Option<Bytes>,
+    retries: u32,
+    backpressure: BackpressureStrategy,
+    /// The send and when it started, once the client has been checked.
+    send: Option<(RetryingSend, Instant)>,
+}
+
+impl ClientSend {
+    fn new(bytes: Bytes, retries: u32, backpressure: BackpressureStrategy) -> Self {
+        Self { bytes: Some(bytes), retries, backpressure, send: None }
+    }
+
+    fn poll<S: EventSink>(&mut self, client: &mut Client<S>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.send.is_none() {
+            let mut bytes = self.bytes.take().expect("ClientSend polled after completion");
+            // The strategy only applies if the client is busy when it is first checked.
+            if self.backpressure != BackpressureStrategy::Block
+                && readiness(&mut client.sender, cx) == Readiness::Busy
+            {
+                match self.backpressure {
+                    BackpressureStrategy::DisconnectClient => client.outcome = Some((bytes, false)),
+                    BackpressureStrategy::DropOldest => client.held = Some(bytes),
+                    _ => {}
+                }
+                return Poll::Ready(());
+            }
+            if let Some(held) = client.held.take() {
+                let mut both = BytesMut::from(&held[..]);
+                both.extend_from_slice(&bytes);
+                bytes = both.freeze();
+            }
+            // The reconnection time is only taken once the client is sure to be sent the bytes, so
+            // that it isn't lost with an event the client wasn't ready for.
+            let bytes = client.with_retry(bytes);
+            self.send = Some((RetryingSend::new(bytes, self.retries), Instant::now()));
+        }
+        let (send, start) = self.send.as_mut().unwrap();
+        let sent = futures::ready!(send.poll(&mut client.sender, cx));
+        client.meta.last_send_latency = Some(start.elapsed());
+        let (send, _) = self.send.take().unwrap();
+        client.outcome = Some((send.into_bytes(), sent));
+        Poll::Ready(())
+    }
+}
+
+/// An SSE server.
+///
+/// By default the clients are Hyper body [`Sender`]s, but a server can send to any
+/// [EventSink](trait.EventSink.html) instead.
+pub struct Server<S = Sender> {
+    name: Option<String>,
+    clients: Vec<Client<S>>,
+    /// The id to give the next client, shared with the servers split off from this one so that
+    /// ids stay unique between them.
+    next_id: Arc<AtomicU64>,
+    track_acks: bool,
+    /// Events with ids kept for replaying, oldest first, with when each was sent.
+    replay: VecDeque<(String, Bytes, Instant)>,
+    replay_capacity: usize,
+    /// How long events stay in the replay buffer.
+    replay_ttl: Option<Duration>,
+    resume_points: HashMap<String, String>,
+    default_event_type: Option<String>,
+    /// The namespace put in front of the types of the events the server builds.
+    event_type_prefix: Option<String>,
+    on_empty: Option<Callback>,
+    on_first_client: Option<Callback>,
+    on_disconnect: Option<ClientCallback>,
+    client_added: Arc<Notify>,
+    send_retries: u32,
+    /// Reused by `broadcast_event` to avoid allocating for every event.
+    scratch: BytesMut,
+    stats: ServerStats,
+    /// Connection counts sampled after each broadcast. Holds up to twice `history_window` samples,
+    /// so that old samples only have to be dropped every `history_window` broadcasts.
+    history: Vec<(Instant, usize)>,
+    history_window: usize,
+    /// The latest event staged for each key by `stage_update`, in the order the keys were first
+    /// staged.
+    staged: Vec<(String, Bytes)>,
+    /// The latest payload given to `stage`, and when it was given.
+    debounced: Option<(Bytes, Instant)>,
+    /// Woken whenever a payload is given to `stage`, for the task spawned by
+    /// `broadcast_debounced`.
+    debounce_staged: Arc<Notify>,
+    /// What `send_heartbeat` sends, an empty comment by default.
+    heartbeat: Bytes,
+    /// The largest event `broadcast_checked` will send, in bytes.
+    max_event_size: Option<usize>,
+    /// The most clients to hold before evicting the least recently active one.
+    max_clients: Option<usize>,
+    /// Whether `broadcast_checked` uses strict validation.
+    strict: bool,
+    /// Whether a broadcast was cancelled before the outcomes of its sends were recorded.
+    unsettled: bool,
+    /// Whether an event being streamed to the clients was dropped before it was closed, so the
+    /// next broadcast has to end it first.
+    unterminated: bool,
+    #[cfg(feature = "metrics")]
+    connection_gauge: ConnectionGauge,
+    /// Whether heartbeats skip clients known to be connected over HTTP/2 or later.
+    http1_heartbeats_only: bool,
+    /// The state of the random number generator used by `send_to_fraction`.
+    rng: u64,
+    /// Whether `send_data_to_clients` gives its events timestamp ids.
+    timestamp_ids: bool,
+    /// The millisecond of the last timestamp id given out, and how many ids were given out in it.
+    last_timestamp: (u64, u32),
+    /// The number of buckets `add_client_with_bucket_key` spreads clients over.
+    buckets: u32,
+    /// What broadcasts do with clients that aren't ready for more data.
+    backpressure: BackpressureStrategy,
+    /// The hash `broadcast_with_content_id` makes ids with.
+    content_hash: fn(&[u8]) -> u64,
+}
+
+/// The outcome of sending to the clients with
+/// [`Server::send_to_clients_no_prune`](struct.Server.html#method.send_to_clients_no_prune) or
+/// [`Server::send_and_flush`](struct.Server.html#method.send_and_flush).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct SendReport {
+    /// The number of clients that were sent the data.
+    pub sent: usize,
+    /// The clients that couldn't be sent the data, in the order they were added.
+    pub failed: Vec<ClientId>,
+    /// The clients that were sent the data, but whose connection didn't confirm taking it in
+    /// time, in the order they were added. Only filled by `send_and_flush`.
+    pub slow: Vec<ClientId>,
+}
+
+/// The outcome of shutting down a server with
+/// [`Server::graceful_shutdown`](struct.Server.html#method.graceful_shutdown).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ShutdownReport {
+    /// The number of clients that took everything they were sent, and whose streams were ended
+    /// cleanly.
+    pub closed: usize,
+    /// The number of clients whose connections were aborted, because they hadn't taken everything
+    /// by the deadline or had already disconnected.
+    pub aborted: usize,
+}
+
+/// What a [Server](struct.Server.html) does when a client can't take an event yet because it
+/// hasn't read the previous ones, set with
+/// [`Server::with_backpressure`](struct.Server.html#method.with_backpressure).
+///
+/// Every strategy but `Block` checks each client without waiting, so one slow client never holds
+/// up the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum BackpressureStrategy {
+    /// Wait for the client to take the event, so the broadcast takes as long as the slowest client.
+    /// No events are lost and no more memory is used, but one stuck client delays every event.
+    /// Before 0.4 every broadcast behaved like this; set this strategy to keep that behavior.
+    Block,
+    /// Remove the client, ending its stream, so that it can reconnect and catch up from the replay
+    /// buffer. This keeps latency and memory low at the cost of the client's connection. This is
+    /// the default, matching how clients that can't be sent to are pruned.
+    #[default]
+    DisconnectClient,
+    /// Skip the event for the client, which keeps its connection and is sent the events broadcast
+    /// once it has caught up. Nothing is held for the client, but it misses events silently.
+    DropNewest,
+    /// Hold the event for the client, replacing any event it already held, and send it along with
+    /// the next event broadcast after the client catches up. Each client holds at most one event,
+    /// so it misses the events in between but gets the latest state it missed, like a conflated
+    /// feed of updates.
+    DropOldest,
+}
+
+/// The state a [Server](struct.Server.html) needs to resume its clients after a restart, from
+/// [`Server::export_state`](struct.Server.html#method.export_state).
+///
+/// All the fields are plain data, so the state can be serialized in whatever format the
+/// application uses and handed to the new process.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ServerState {
+    /// The ids of the clients that were connected.
+    pub clients: Vec<ClientId>,
+    /// The events in the replay buffer, oldest first, each with its id.
+    pub replay: Vec<(String, Bytes)>,
+    /// The last event id sent to each resumable client's key.
+    pub resume_points: HashMap<String, String>,
+}
+
+/// Statistics about everything a [Server](struct.Server.html) has sent over its lifetime, from
+/// [`Server::stats`](struct.Server.html#method.stats).
+///
+/// The counters saturate at `u64::MAX` instead of overflowing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ServerStats {
+    /// The number of times data has been broadcast to the clients. Every call to
+    /// `send_to_clients` counts, including heartbeats.
+    pub events_broadcast: u64,
+    /// The total number of bytes successfully sent, summed over all clients.
+    pub bytes_sent: u64,
+}
+
+impl ServerStats {
+    /// Count a broadcast, also reporting it to the `metrics` facade if that feature is enabled.
+    fn record_broadcast(&mut self) {
+        self.events_broadcast = self.events_broadcast.saturating_add(1);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("sse_events_sent_total").increment(1);
+    }
+    /// Count bytes sent to the clients, also reporting them to the `metrics` facade if that
+    /// feature is enabled.
+    fn record_bytes_sent(&mut self, bytes: u64) {
+        self.bytes_sent = self.bytes_sent.saturating_add(bytes);
+        #[cfg(feature = "metrics")]
+        metrics::counter!("sse_bytes_sent_total").increment(bytes);
+    }
+}
+
+impl Server {
+    /// Create a new server with no clients.
+    ///
+    /// To create a server whose clients are another [EventSink](trait.EventSink.html), use
+    /// `Server::<Sink>::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new server with no clients, with room for at least `capacity` clients before it
+    /// has to reallocate, like `Vec::with_capacity`. Otherwise this is the same as
+    /// [`new`](#method.new). Room for more clients can be made later with
+    /// [`reserve`](#method.reserve).
+    ///
+    /// # Examples
+    /// ```
+    /// let server = hyper_usse::Server::with_capacity(100);
+    /// assert!(server.capacity() >= 100);
+    /// assert_eq!(server.connections(), 0);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            clients: Vec::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Create a response body that adds itself to `server` as a client once it is first polled,
+    /// that is once Hyper starts sending it to the client.
+    ///
+    /// A body added eagerly with [`add_client`](#method.add_client) counts as a connection as soon
+    /// as it is created, even if the request is abandoned before the response is sent, until the
+    /// next broadcast prunes it. A lazily registered body is only counted once it is actually
+    /// being streamed, which makes [`connections`](#method.connections) more accurate. On the
+    /// other hand the client may miss events broadcast between the request being handled and the
+    /// body being polled, and registering has to wait for the server's lock, so the first poll
+    /// can be delayed by a broadcast that is in progress.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use std::sync::Arc;
+    /// use futures::StreamExt;
+    /// use tokio::sync::Mutex;
+    /// use hyper_usse::Server;
+    ///
+    /// let server = Arc::new(Mutex::new(Server::new()));
+    /// let mut body = Server::lazy_client(Arc::clone(&server));
+    /// assert_eq!(server.lock().await.connections(), 0);
+    ///
+    /// let _ = futures::poll!(body.next());
+    /// assert_eq!(server.lock().await.connections(), 1);
+    ///
+    /// server.lock().await.send_to_clients(hyper_usse::EventBuilder::new("Hello")).await;
+    /// assert_eq!(body.next().await.unwrap().unwrap(), "data: Hello\n\n");
+    /// # });
+    /// ```
+    pub fn lazy_client(server: Arc<Mutex<Self>>) -> Body {
+        let (sender, body) = Body::channel();
+        let register = async move {
+            server.lock().await.add_client(sender);
+            None::<Result<Bytes, hyper::Error>>
+        };
+        Body::wrap_stream(stream::once(register).filter_map(future::ready).chain(body))
+    }
+}
+
+impl<S> Default for Server<S> {
+    fn default() -> Self {
+        Server {
+            name: None,
+            clients: Vec::new(),
+            next_id: Arc::new(AtomicU64::new(0)),
+            track_acks: false,
+            replay: VecDeque::new(),
+            replay_capacity: 0,
+            replay_ttl: None,
+            resume_points: HashMap::new(),
+            default_event_type: None,
+            event_type_prefix: None,
+            on_empty: None,
+            on_first_client: None,
+            on_disconnect: None,
+            client_added: Arc::new(Notify::new()),
+            send_retries: 0,
+            scratch: BytesMut::new(),
+            stats: ServerStats::default(),
+            history: Vec::new(),
+            history_window: 0,
+            staged: Vec::new(),
+            debounced: None,
+            debounce_staged: Arc::new(Notify::new()),
+            heartbeat: Bytes::from_static(b":\n\n"),
+            max_event_size: None,
+            max_clients: None,
+            strict: false,
+            unsettled: false,
+            unterminated: false,
+            #[cfg(feature = "metrics")]
+            connection_gauge: ConnectionGauge::default(),
+            http1_heartbeats_only: false,
+            rng: random_seed(),
+            timestamp_ids: false,
+            last_timestamp: (0, 0),
+            buckets: 1,
+            backpressure: BackpressureStrategy::default(),
+            content_hash: fnv1a,
+        }
+    }
+}
+
+impl<S: EventSink> Server<S> {
+    /// Give the server a name, to tell it apart from the other servers in an application. The name
+    /// is included in the server's `Debug` output, and starts its `Display` output, a one-line
+    /// summary of its connections and [stats](#method.stats) for logs.
+    /// [ServerStats](struct.ServerStats.html) itself doesn't include the name, so that it stays
+    /// `Copy`.
+    ///
+    /// # Examples
+    /// ```
+    /// let server = hyper_usse::Server::new().with_name("notifications");
+    /// assert_eq!(server.name(), Some("notifications"));
+    /// assert!(format!("{:?}", server).starts_with("Server { name: Some(\"notifications\")"));
+    /// assert_eq!(
+    ///     server.to_string(),
+    ///     "notifications: 0 connections, 0 events broadcast, 0 bytes sent",
+    /// );
+    /// ```
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Get the name given to the server with [`with_name`](#method.with_name).
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Hold at most `limit` clients. When a client is added to a full server, the least recently
+    /// active client (the one that has gone longest without being sent anything, or the oldest
+    /// one if they all have) is disconnected to make room, so fresh connections are preferred
+    /// over stale ones.
+    ///
+    /// # Panics
+    /// Panics if `limit` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new().with_max_clients_lru(2);
+    /// let (client, oldest) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// assert_eq!(server.connections(), 2);
+    /// assert!(hyper::body::to_bytes(oldest).await.is_err());
+    /// # });
+    /// ```
+    pub fn with_max_clients_lru(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "the client limit must be at least 1");
+        self.max_clients = Some(limit);
+        self
+    }
+
+    /// Spread the clients added with
+    /// [`add_client_with_bucket_key`](#method.add_client_with_bucket_key) over `count` buckets,
+    /// numbered from 0, for A/B testing. There is a single bucket by default.
+    ///
+    /// # Panics
+    /// Panics if `count` is zero.
+    pub fn with_buckets(mut self, count: u32) -> Self {
+        assert!(count > 0, "there must be at least 1 bucket");
+        self.buckets = count;
+        self
+    }
+
+    /// Make [`broadcast_checked`](#method.broadcast_checked) reject events that are longer than
+    /// `max` bytes once built.
+    pub fn with_max_event_size(mut self, max: usize) -> Self {
+        self.max_event_size = Some(max);
+        self
+    }
+
+    /// Make [`broadcast_checked`](#method.broadcast_checked) check events with
+    /// [`EventBuilder::validate_strict`](struct.EventBuilder.html#method.validate_strict), so that
+    /// events whose data contains a blank line are rejected.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{EventBuilder, EventError};
+    /// let mut server = hyper_usse::Server::new().with_strict_validation();
+    /// let event = EventBuilder::new("data: Already framed\n\n");
+    /// assert_eq!(server.broadcast_checked(&event).await, Err(EventError::BlankLineInData));
+    /// # });
+    /// ```
+    pub fn with_strict_validation(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Send `event` as the heartbeat instead of an empty comment, for clients that only count
+    /// real events as activity. Comments are invisible to the client's code, while a heartbeat
+    /// event fires a handler for its event type on every heartbeat; give it a type such as `ping`
+    /// so that it doesn't reach `onmessage`.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let ping = EventBuilder::new("\n").event_type("ping");
+    /// let mut server = hyper_usse::Server::new().with_heartbeat_event(ping);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.send_heartbeat().await;
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "event: ping\ndata: \n\n");
+    /// # });
+    /// ```
+    pub fn with_heartbeat_event(mut self, event: OwnedEventBuilder) -> Self {
+        self.heartbeat = event.into();
+        self
+    }
+
+    /// Only send heartbeats to clients connected over HTTP/1. HTTP/2 connections have their own
+    /// `PING` frames to keep them alive, so heartbeats are redundant for them. Only clients added
+    /// with [`add_client_with_version`](#method.add_client_with_version) are known to be
+    /// connected over HTTP/2; clients whose version is unknown are still sent heartbeats.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use hyper::{HeaderMap, Version};
+    ///
+    /// let mut server = hyper_usse::Server::new().with_http1_heartbeats_only();
+    /// let (client, http1_body) = hyper::Body::channel();
+    /// server.add_client_with_version(client, Version::HTTP_11);
+    /// let (client, http2_body) = hyper::Body::channel();
+    /// server.add_client_with_version(client, Version::HTTP_2);
+    ///
+    /// assert_eq!(server.send_heartbeat().await, 2);
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(http1_body).await.unwrap(), ":\n\n");
+    /// assert_eq!(hyper::body::to_bytes(http2_body).await.unwrap(), "");
+    /// # });
+    /// ```
+    pub fn with_http1_heartbeats_only(mut self) -> Self {
+        self.http1_heartbeats_only = true;
+        self
+    }
+
+    /// Give the events sent with [`send_data_to_clients`](#method.send_data_to_clients) timestamp
+    /// ids, like [`send_timestamped`](#method.send_timestamped) does.
+    pub fn with_timestamp_ids(mut self) -> Self {
+        self.timestamp_ids = true;
+        self
+    }
+
+    /// Seed the random number generator that picks the clients for
+    /// [`send_to_fraction`](#method.send_to_fraction), so that the same clients are picked every
+    /// time, such as in tests. By default it is seeded randomly.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.rng = seed;
+        self
+    }
+
+    /// Set the event type given to events sent with
+    /// [`send_data_to_clients`](#method.send_data_to_clients). Events sent with `send_to_clients`
+    /// are not affected, so an event built with its own type keeps it.
+    pub fn with_default_event_type(mut self, event_type: impl Into<String>) -> Self {
+        self.default_event_type = Some(event_type.into());
+        self
+    }
+
+    /// Namespace the types of the events sent with [`broadcast_event`](#method.broadcast_event) and
+    /// the other methods that take events rather than bytes, by putting `prefix` and a `.` in
+    /// front of them. With the prefix `chat`, an event with the type `message` is sent as
+    /// `chat.message`. This lets modules of an application share a server without repeating their
+    /// namespace everywhere.
+    ///
+    /// An event type that starts with a `.` is absolute: it is sent without the prefix and without
+    /// the leading `.`, so `.system` is sent as `system`. Events without a type, and events sent
+    /// as bytes with `send_to_clients`, are never prefixed.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{BackpressureStrategy, EventBuilder};
+    /// let mut server = hyper_usse::Server::new()
+    ///     .with_event_type_prefix("chat")
+    ///     .with_backpressure(BackpressureStrategy::Block);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// let producer = async move {
+    ///     server.broadcast_event(&EventBuilder::new("Hi").event_type("message")).await;
+    ///     server.broadcast_event(&EventBuilder::new("Bye").event_type(".system")).await;
+    /// };
+    /// let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(
+    ///     received.unwrap(),
+    ///     "event: chat.message\ndata: Hi\n\nevent: system\ndata: Bye\n\n",
+    /// );
+    /// # });
+    /// ```
+    pub fn with_event_type_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.event_type_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Choose what broadcasts do when a client can't take an event yet, because it hasn't read the
+    /// events already sent to it. See [BackpressureStrategy](enum.BackpressureStrategy.html) for
+    /// the strategies; the default is to disconnect the client.
+    ///
+    /// The strategy is used by every method that sends to several clients. It only applies to
+    /// whole events: the parts of an event sent by
+    /// [`open_event`](#method.open_event) and [`broadcast_chunked`](#method.broadcast_chunked) are
+    /// always waited for, and a client's pending reconnection time is kept until it is actually
+    /// sent an event.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use futures::StreamExt;
+    /// # use hyper_usse::{BackpressureStrategy, EventBuilder};
+    /// let strategy = BackpressureStrategy::DropOldest;
+    /// let mut server = hyper_usse::Server::new().with_backpressure(strategy);
+    /// let (client, mut body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// // The client doesn't read the first event in time, so the second is held for it and then
+    /// // replaced by the third.
+    /// for number in 1..=3 {
+    ///     server.send_to_clients(EventBuilder::new(number.to_string())).await;
+    /// }
+    /// assert_eq!(body.next().await.unwrap().unwrap(), "data: 1\n\n");
+    ///
+    /// server.send_to_clients(EventBuilder::new("4")).await;
+    /// assert_eq!(body.next().await.unwrap().unwrap(), "data: 3\n\ndata: 4\n\n");
+    /// # });
+    /// ```
+    pub fn with_backpressure(mut self, strategy: BackpressureStrategy) -> Self {
+        self.backpressure = strategy;
+        self
+    }
+
+    /// Set the hash that [`broadcast_with_content_id`](#method.broadcast_with_content_id) makes ids
+    /// with, such as a faster or stronger one than the default 64-bit FNV-1a. The hash should give
+    /// the same value for the same data every time, including across restarts and versions of the
+    /// program, or ids won't stay stable.
+    pub fn with_content_hash(mut self, hash: fn(&[u8]) -> u64) -> Self {
+        self.content_hash = hash;
+        self
+    }
+
+    /// Retry failed sends to a client up to `retries` times, with a short backoff between attempts,
+    /// before the client is dropped. Only [`SinkError::Transient`](enum.SinkError.html) errors are
+    /// retried; a client whose connection has closed is dropped straight away.
+    ///
+    /// Note that `hyper::body::Sender` currently reports every failure as a closed channel, so for
+    /// Hyper clients this only takes effect if a future version of Hyper reports transient errors.
+    /// Retries require a Tokio timer to be running.
+    pub fn with_send_retries(mut self, retries: u32) -> Self {
+        self.send_retries = retries;
+        self
+    }
+
+    /// Set a callback to run whenever the server loses its last client, either because it was
+    /// pruned during a broadcast or because it was disconnected. This is useful to pause expensive
+    /// event generation until someone connects again.
+    ///
+    /// The callback runs exactly once each time the number of clients drops to zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let emptied = Arc::new(AtomicUsize::new(0));
+    /// let mut server = hyper_usse::Server::new();
+    /// let counter = Arc::clone(&emptied);
+    /// server.on_empty(move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// });
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// drop(body);
+    /// server.send_heartbeat().await;
+    /// server.send_heartbeat().await;
+    /// assert_eq!(emptied.load(Ordering::SeqCst), 1);
+    /// # });
+    /// ```
+    pub fn on_empty(&mut self, callback: impl Fn() + Send + 'static) {
+        self.on_empty = Some(Box::new(callback));
+    }
+
+    /// Set a callback to run whenever `add_client` adds a client to a server that had none.
+    ///
+    /// The callback runs exactly once each time the number of clients goes from zero to one.
+    pub fn on_first_client(&mut self, callback: impl Fn() + Send + 'static) {
+        self.on_first_client = Some(Box::new(callback));
+    }
+
+    /// Get a notifier that is notified every time a client is added to the server. This lets an
+    /// event producing loop wait until there is someone to send events to, instead of generating
+    /// events into the void.
+    ///
+    /// Each notification wakes one waiting task. If no task is waiting, the notification is stored
+    /// until the next call to `notified`, so a client added just before the producer starts waiting
+    /// isn't missed. Don't hold a lock on the server while waiting, otherwise clients can't be
+    /// added.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # async fn example(server: std::sync::Arc<tokio::sync::Mutex<hyper_usse::Server>>) {
+    /// loop {
+    ///     let client_added = server.lock().await.client_notifier();
+    ///     if server.lock().await.connections() == 0 {
+    ///         client_added.notified().await;
+    ///     }
+    ///     server.lock().await.send_to_clients(hyper_usse::EventBuilder::new("Data")).await;
+    /// }
+    /// # }
+    /// ```
+    pub fn client_notifier(&self) -> Arc<Notify> {
+        Arc::clone(&self.client_added)
+    }
+
+    /// Keep track of the events with ids that each client has been sent, until the client
+    /// acknowledges them with [`ack`](#method.ack). This is the server side of at-least-once
+    /// delivery: clients report which events they have received through some other channel (such
+    /// as a POST endpoint), and anything they haven't acknowledged can be resent.
+    ///
+    /// Only complete events with an `id` field sent through `send_to_clients` are tracked.
+    /// Unacknowledged events are kept until the client disconnects.
+    pub fn with_ack_tracking(mut self) -> Self {
+        self.track_acks = true;
+        self
+    }
+
+    /// Keep the last `capacity` events with ids that were sent to the clients, so that they can be
+    /// replayed to clients that reconnect with
+    /// [`add_client_with_last_id`](#method.add_client_with_last_id) or
+    /// [`register_resumable`](#method.register_resumable).
+    ///
+    /// Only complete events with an `id` field sent through `send_to_clients` are kept.
+    pub fn with_replay_buffer(mut self, capacity: usize) -> Self {
+        self.replay_capacity = capacity;
+        self
+    }
+
+    /// Expire events from the replay buffer once they are older than `ttl`, as well as when it is
+    /// full, so that a client that comes back after a long time isn't replayed stale events. A
+    /// client whose last event has expired isn't replayed anything, like one whose last event was
+    /// pushed out of the buffer.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use std::time::Duration;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new()
+    ///     .with_replay_buffer(16)
+    ///     .with_replay_ttl(Duration::from_millis(10));
+    /// server.send_to_clients(EventBuilder::new("First").id("1")).await;
+    /// server.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    /// std::thread::sleep(Duration::from_millis(20));
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client_with_last_id(client, Some("1"));
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "");
+    /// # });
+    /// ```
+    pub fn with_replay_ttl(mut self, ttl: Duration) -> Self {
+        self.replay_ttl = Some(ttl);
+        self
+    }
+
+    /// Record the number of connections after every `send_to_clients`, keeping the last `window`
+    /// samples. They are available from [`connection_history`](#method.connection_history).
+    pub fn with_connection_history(mut self, window: usize) -> Self {
+        self.history_window = window;
+        self
+    }
+
+    /// Add a client to a server. For Hyper, a `Sender` can be obtained by calling
+    /// `Body::channel()`.
+    ///
+    /// The server only finds out that a client has disconnected when it next tries to send to it,
+    /// so a client stays in [`connections`](#method.connections) until the next broadcast or
+    /// heartbeat after it goes away. Use [`prune`](#method.prune) to check without sending.
+    ///
+    /// Adding a client borrows the server mutably, so when the server is shared behind a lock or
+    /// [spawned](#method.spawn) a client can only be added between broadcasts, never during one. A
+    /// client added while other tasks are broadcasting is sent every event broadcast after it was
+    /// added, and none from before; it is never sent part of an event.
+    ///
+    /// This function returns the id of the new client.
+    pub fn add_client(&mut self, client: S) -> ClientId {
+        self.add_client_with_last_id(client, None)
+    }
+
+    /// Add a client to a server, first sending it every event in the replay buffer that came after
+    /// the event with the id `last_event_id`. This is usually the value of the request's
+    /// `Last-Event-ID` header, which browsers send when reconnecting.
+    ///
+    /// Nothing is replayed if `last_event_id` is `None` or isn't in the replay buffer, or if the
+    /// server has no replay buffer (see [`with_replay_buffer`](#method.with_replay_buffer)). The
+    /// replayed events are queued on the client without waiting, so the client must not have been
+    /// sent anything yet.
+    ///
+    /// This function returns the id of the new client.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_replay_buffer(16);
+    /// server.send_to_clients(EventBuilder::new("First").id("1")).await;
+    /// server.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client_with_last_id(client, Some("1"));
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "id: 2\ndata: Second\n\n");
+    /// # });
+    /// ```
+    pub fn add_client_with_last_id(&mut self, client: S, last_event_id: Option<&str>) -> ClientId {
+        let mut options = ClientOptions::new();
+        if let Some(last_event_id) = last_event_id {
+            options = options.with_last_event_id(last_event_id);
+        }
+        self.add_client_with(client, options)
+    }
+
+    /// Add a client to a server with any combination of the attributes the other `add_client`
+    /// methods set, such as a client for a user that is also subscribed to a topic. Each attribute
+    /// works the same as in the method that sets it alone.
+    ///
+    /// This function returns the id of the new client.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{ClientOptions, EventBuilder};
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let options = ClientOptions::new()
+    ///     .with_user("alice")
+    ///     .with_topic("sports")
+    ///     .with_version(hyper::Version::HTTP_2);
+    /// let id = server.add_client_with(client, options);
+    ///
+    /// let meta = server.into_iter().find(|(client, _)| *client == id).unwrap().1;
+    /// assert_eq!(meta.user_id.as_deref(), Some("alice"));
+    /// assert_eq!(meta.topic.as_deref(), Some("sports"));
+    ///
+    /// server.send_to_topic("sports", EventBuilder::new("Goal!")).await;
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "data: Goal!\n\n");
+    /// # });
+    /// ```
+    pub fn add_client_with(&mut self, mut client: S, options: ClientOptions) -> ClientId {
+        let ClientOptions {
+            last_event_id,
+            resume_key,
+            label,
+            topic,
+            global,
+            user_id,
+            version,
+            bucket_key,
+            dedup_key,
+        } = options;
+        if let Some(dedup_key) = &dedup_key {
+            self.remove_clients(|other| other.meta.dedup_key.as_ref() == Some(dedup_key), true);
+        }
+
+        let last_event_id = last_event_id
+            .or_else(|| resume_key.as_ref().and_then(|key| self.resume_points.get(key).cloned()));
+        if let Some(last_event_id) = last_event_id {
+            self.expire_replay();
+            if let Some(i) = self.replay.iter().position(|(id, ..)| *id == last_event_id) {
+                let replayed: Vec<u8> = self.replay
+                    .iter()
+                    .skip(i + 1)
+                    .flat_map(|(_, event, _)| event.iter().copied())
+                    .collect();
+                if !replayed.is_empty() {
+                    try_send(&mut client, replayed.into());
+                }
+            }
+        }
+
+        let bucket = bucket_key.map(|key| (fnv1a(&key) % u64::from(self.buckets)) as u32);
+        let id = ClientId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.clients.push(Client {
+            meta: ClientMeta {
+                id,
+                connected_at: Instant::now(),
+                label,
+                resume_key,
+                last_sent: None,
+                topic,
+                global,
+                user_id,
+                last_send_latency: None,
+                version,
+                bucket,
+                dedup_key,
+            },
+            sender: client,
+            pending: VecDeque::new(),
+            outcome: None,
+            retry: None,
+            held: None,
+        });
+        self.report_connections();
+        self.client_added.notify();
+        if self.clients.len() == 1 {
+            if let Some(on_first_client) = &self.on_first_client {
+                on_first_client();
+            }
+        }
+        if self.max_clients.is_some_and(|max| self.clients.len() > max) {
+            let evicted = self.clients[..self.clients.len() - 1]
+                .iter()
+                .min_by_key(|client| {
+                    let last_active = client.meta.last_sent.unwrap_or(client.meta.connected_at);
+                    (last_active, client.meta.id)
+                })
+                .map(|client| client.meta.id)
+                .unwrap();
+            self.remove_clients(|client| client.meta.id == evicted, true);
+        }
+        id
+    }
+
+    /// Add a client identified by a stable `key` that outlives its connections, such as a session
+    /// id. The server remembers the id of the last event each resumable client was sent, so when a
+    /// client reconnects with the same key it is replayed everything it missed from the replay
+    /// buffer, without relying on it sending the `Last-Event-ID` header. See
+    /// [`add_client_with_last_id`](#method.add_client_with_last_id) for how events are replayed.
+    ///
+    /// The last event id of each key is remembered until it is removed with
+    /// [`forget_resumable`](#method.forget_resumable).
+    ///
+    /// This function returns the id of the new client.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_replay_buffer(16);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.register_resumable("session", client);
+    /// server.send_to_clients(EventBuilder::new("First").id("1")).await;
+    ///
+    /// // The client disconnects and misses an event.
+    /// drop(body);
+    /// server.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// server.register_resumable("session", client);
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "id: 2\ndata: Second\n\n");
+    /// # });
+    /// ```
+    pub fn register_resumable(&mut self, key: impl Into<String>, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_resume_key(key))
+    }
+
+    /// Add a client with a label, such as the name of the user it belongs to. The label is
+    /// available in the client's [ClientMeta](struct.ClientMeta.html).
+    ///
+    /// This function returns the id of the new client.
+    pub fn add_labeled_client(&mut self, label: impl Into<String>, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_label(label))
+    }
+
+    /// Add a client that subscribes to a topic. Besides everything sent with
+    /// [`send_to_clients`](#method.send_to_clients), the client receives the events sent to its
+    /// topic with [`send_to_topic`](#method.send_to_topic).
+    ///
+    /// This function returns the id of the new client.
+    pub fn add_client_to_topic(&mut self, topic: impl Into<String>, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_topic(topic))
+    }
+
+    /// Add a client that receives the events sent to every topic, as well as everything sent with
+    /// [`send_to_clients`](#method.send_to_clients), such as an admin monitor. Global clients
+    /// aren't counted by [`connections_in_topic`](#method.connections_in_topic).
+    ///
+    /// This function returns the id of the new client.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{BackpressureStrategy, EventBuilder};
+    /// let mut server = hyper_usse::Server::new().with_backpressure(BackpressureStrategy::Block);
+    /// let (monitor, body) = hyper::Body::channel();
+    /// server.add_global_client(monitor);
+    ///
+    /// let producer = async move {
+    ///     server.send_to_topic("sports", EventBuilder::new("Goal!")).await;
+    ///     server.send_to_topic("news", EventBuilder::new("Extra!")).await;
+    /// };
+    /// let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(received.unwrap(), "data: Goal!\n\ndata: Extra!\n\n");
+    /// # });
+    /// ```
+    pub fn add_global_client(&mut self, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().global())
+    }
+
+    /// Add a client along with the HTTP version of its connection, which can be found with the
+    /// request's `version` method.
+    /// The version is used by [`with_http1_heartbeats_only`](#method.with_http1_heartbeats_only) to
+    /// skip heartbeats on connections that don't need them.
+    ///
+    /// This function returns the id of the new client.
+    pub fn add_client_with_version(&mut self, client: S, version: Version) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_version(version))
+    }
+
+    /// Add a client to the bucket picked by hashing `key`, such as the id of its user. The same key
+    /// is always put in the same bucket, as long as the number of buckets set by
+    /// [`with_buckets`](#method.with_buckets) doesn't change. So unlike with
+    /// [`send_to_fraction`](#method.send_to_fraction), the clients sent to by
+    /// [`send_to_bucket`](#method.send_to_bucket) are the same every time, even across
+    /// reconnections. The key's bytes are hashed with FNV-1a, which never changes, so buckets also
+    /// stay the same across restarts and builds with different versions of Rust.
+    ///
+    /// This function returns the id of the new client.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_buckets(2);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client_with_bucket_key("alice", client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client_with_bucket_key("alice", client);
+    ///
+    /// // Both of alice's clients are in the same bucket.
+    /// let variant_a = server.send_to_bucket(0, EventBuilder::new("Variant A")).await;
+    /// let variant_b = server.send_to_bucket(1, EventBuilder::new("Variant B")).await;
+    /// assert!(variant_a == 2 && variant_b == 0 || variant_a == 0 && variant_b == 2);
+    /// # });
+    /// ```
+    pub fn add_client_with_bucket_key(&mut self, key: impl AsRef<[u8]>, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_bucket_key(key))
+    }
+
+    /// Add a client belonging to an authenticated user. A user can have several clients, such as
+    /// one for each of their devices or tabs, which can be sent to together with
+    /// [`send_to_user`](#method.send_to_user).
+    ///
+    /// This function returns the id of the new client.
+    pub fn add_client_for_user(&mut self, user_id: impl Into<String>, client: S) -> ClientId {
+        self.add_client_with(client, ClientOptions::new().with_user(user_id))
+    }
+
+    /// Add a client identified by a `dedup_key` that the client supplies, such as a tab or session
+    /// id, replacing any client already connected with the same key. The old connection is
+    /// aborted before the new client is added, so that a page that reloaded before its old
+    /// connection closed still only has one connection.
+    ///
+    /// This function returns the id of the new client and whether an old connection was replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, old) = hyper::Body::channel();
+    /// let (_, replaced) = server.add_client_dedup("tab-1", client);
+    /// assert!(!replaced);
+    ///
+    /// let (client, _body) = hyper::Body::channel();
+    /// let (_, replaced) = server.add_client_dedup("tab-1", client);
+    /// assert!(replaced);
+    /// assert_eq!(server.connections(), 1);
+    /// assert!(hyper::body::to_bytes(old).await.is_err());
+    /// # });
+    /// ```
+    pub fn add_client_dedup(
+        &mut self,
+        dedup_key: impl Into<String>,
+        client: S,
+    ) -> (ClientId, bool) {
+        let dedup_key = dedup_key.into();
+        let replaced = self
+            .clients
+            .iter()
+            .any(|client| client.meta.dedup_key.as_ref() == Some(&dedup_key));
+        (self.add_client_with(client, ClientOptions::new().with_dedup_key(dedup_key)), replaced)
+    }
+
+    /// Forget the last event id remembered for a resumable client's key.
+    pub fn forget_resumable(&mut self, key: &str) {
+        self.resume_points.remove(key);
+    }
+
+    /// Acknowledge that a client has received every event up to and including the one with the
+    /// id `up_to_id`, dropping them from the client's pending events.
+    ///
+    /// This function returns `false` if the client isn't connected or no pending event has that
+    /// id, in which case nothing is dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{BackpressureStrategy, EventBuilder};
+    /// let mut server = hyper_usse::Server::new()
+    ///     .with_ack_tracking()
+    ///     .with_backpressure(BackpressureStrategy::Block);
+    /// let (client, body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    ///
+    /// let producer = async move {
+    ///     server.send_to_clients(EventBuilder::new("First").id("1")).await;
+    ///     server.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    ///     assert_eq!(server.pending_events(id).len(), 2);
+    ///
+    ///     assert!(server.ack(id, "1"));
+    ///     assert_eq!(server.pending_events(id), vec!["id: 2\ndata: Second\n\n"]);
+    /// };
+    /// futures::join!(producer, hyper::body::to_bytes(body));
+    /// # });
+    /// ```
+    pub fn ack(&mut self, client: ClientId, up_to_id: &str) -> bool {
+        let client = match self.clients.iter_mut().find(|c| c.meta.id == client) {
+            Some(client) => client,
+            None => return false,
+        };
+        match client.pending.iter().position(|(id, _)| id == up_to_id) {
+            Some(i) => {
+                client.pending.drain(..=i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the events sent to a client that it hasn't acknowledged yet, oldest first. This is empty
+    /// if the client isn't connected or the server doesn't track acknowledgements.
+    pub fn pending_events(&self, client: ClientId) -> Vec<Bytes> {
+        self.clients
+            .iter()
+            .find(|c| c.meta.id == client)
+            .map(|client| client.pending.iter().map(|(_, event)| event.slice(..)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the events in the replay buffer that came after the event with the id `last_id`, oldest
+    /// first, or every event in it if `last_id` is `None`. This is what
+    /// [`add_client_with_last_id`](#method.add_client_with_last_id) replays, so it can be used to
+    /// serve clients that poll for events instead of streaming them, such as from an
+    /// `/events?since=X` endpoint, from the same buffer.
+    ///
+    /// This is empty if `last_id` isn't in the replay buffer, such as because it was pushed out or
+    /// expired, or if the server has no replay buffer (see
+    /// [`with_replay_buffer`](#method.with_replay_buffer)). Expired events are never returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_replay_buffer(16);
+    /// server.send_to_clients(EventBuilder::new("First").id("1")).await;
+    /// server.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    ///
+    /// assert_eq!(server.events_since(Some("1")), ["id: 2\ndata: Second\n\n"]);
+    /// assert_eq!(server.events_since(None).len(), 2);
+    /// assert!(server.events_since(Some("unknown")).is_empty());
+    /// # });
+    /// ```
+    pub fn events_since(&self, last_id: Option<&str>) -> Vec<Bytes> {
+        let now = Instant::now();
+        let live = self
+            .replay
+            .iter()
+            .skip_while(|(.., sent)| self.replay_ttl.is_some_and(|ttl| now - *sent >= ttl));
+        let mut live: Vec<_> = live.collect();
+        if let Some(last_id) = last_id {
+            match live.iter().position(|(id, ..)| id == last_id) {
+                Some(i) => {
+                    live.drain(..=i);
+                }
+                None => return Vec::new(),
+            }
+        }
+        live.into_iter().map(|(_, event, _)| event.slice(..)).collect()
+    }
+
+    /// Send some text to the clients. Most often, this text is made using an
+    /// [EventBuilder](struct.EventBuilder.html). This will automatically remove all disconnected
+    /// clients.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Cancellation
+    /// The text is sent to all the clients at once, so if the future is dropped before it
+    /// completes, such as by losing a `tokio::select!`, some clients will have been sent the text
+    /// and others won't. No client is ever sent part of it. The text is added to the replay
+    /// buffer as soon as sending starts, and the server records which clients were sent it and
+    /// removes the ones that had disconnected at the start of the next broadcast, so the server
+    /// is never left inconsistent. Until then [`connections`](#method.connections) may count the
+    /// disconnected clients.
+    ///
+    /// The same applies to every other method that sends to the clients.
+    pub async fn send_to_clients<B: Into<Bytes>>(&mut self, text: B) -> usize {
+        let bytes = text.into();
+        self.push_replay(bytes.slice(..));
+        self.send_where(bytes, |_| true).await;
+        self.sample_history();
+        self.clients.len()
+    }
+
+    /// Send some text to the clients like [`send_to_clients`](#method.send_to_clients), but only
+    /// send to `concurrency` clients at once. `send_to_clients` sends to every client at once,
+    /// which for tens of thousands of clients holds a send in memory for each of them; this bounds
+    /// the memory used by a large broadcast, at the cost of a slow client holding up one of the
+    /// `concurrency` slots, which makes the broadcast take longer.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Panics
+    /// Panics if `concurrency` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let bodies: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let (client, body) = hyper::Body::channel();
+    ///         server.add_client(client);
+    ///         body
+    ///     })
+    ///     .collect();
+    ///
+    /// assert_eq!(server.send_to_clients_buffered(EventBuilder::new("Hello"), 2).await, 4);
+    /// drop(server);
+    /// for body in bodies {
+    ///     assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "data: Hello\n\n");
+    /// }
+    /// # });
+    /// ```
+    pub async fn send_to_clients_buffered<B>(&mut self, text: B, concurrency: usize) -> usize
+    where
+        B: Into<Bytes>,
+    {
+        assert!(concurrency > 0, "the concurrency must be at least 1");
+        let bytes = text.into();
+        self.push_replay(bytes.slice(..));
+        self.send_each(|_| Some(bytes.slice(..)), true, Some(concurrency), self.backpressure).await;
+        self.sample_history();
+        self.clients.len()
+    }
+
+    /// Record the number of connections after a broadcast, if the server keeps a history.
+    fn sample_history(&mut self) {
+        if self.history_window > 0 {
+            if self.history.len() == self.history_window * 2 {
+                self.history.drain(..self.history_window);
+            }
+            self.history.push((Instant::now(), self.clients.len()));
+        }
+    }
+
+    /// Send bytes that are already framed as SSE, such as several events built ahead of time and
+    /// joined together, to the clients exactly as they are. No framing is added, so the bytes must
+    /// be made of complete events; use [`send_raw_checked`](#method.send_raw_checked) to check
+    /// that they are.
+    ///
+    /// This does the same as [`send_to_clients`](#method.send_to_clients), but says that the
+    /// bytes aren't a single event.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.send_raw_to_clients("data: one\n\ndata: two\n\n").await;
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "data: one\n\ndata: two\n\n");
+    /// # });
+    /// ```
+    pub async fn send_raw_to_clients(&mut self, raw: impl Into<Bytes>) -> usize {
+        self.send_to_clients(raw).await
+    }
+
+    /// Send pre-framed bytes to the clients like
+    /// [`send_raw_to_clients`](#method.send_raw_to_clients), after checking that they end with a
+    /// blank line. Nothing is sent if they don't.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Errors
+    /// Fails with [`EventError::Unterminated`](enum.EventError.html) if the bytes don't end with
+    /// `\n\n`.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventError;
+    /// let mut server = hyper_usse::Server::new();
+    /// assert_eq!(server.send_raw_checked("data: one\n").await, Err(EventError::Unterminated));
+    /// assert_eq!(server.send_raw_checked("data: one\n\n").await, Ok(0));
+    /// # });
+    /// ```
+    pub async fn send_raw_checked(&mut self, raw: impl Into<Bytes>) -> Result<usize, EventError> {
+        let raw = raw.into();
+        if !raw.ends_with(b"\n\n") {
+            return Err(EventError::Unterminated);
+        }
+        Ok(self.send_raw_to_clients(raw).await)
+    }
+
+    /// Send some text to the clients only if at least `min` clients are connected, returning the
+    /// number of currently connected clients if it was sent and `None` otherwise. The text isn't
+    /// converted to bytes unless it is sent, so an `EventBuilder` isn't built for nobody.
+    ///
+    /// The count this checks is that of [`connections`](#method.connections), so it can include
+    /// clients that have disconnected since the last broadcast. To avoid computing an expensive
+    /// payload for nobody, check `connections` before computing it.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// assert_eq!(server.send_if_clients(1, EventBuilder::new("Hello")).await, None);
+    ///
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// assert_eq!(server.send_if_clients(1, EventBuilder::new("Hello")).await, Some(1));
+    /// # });
+    /// ```
+    pub async fn send_if_clients<B: Into<Bytes>>(&mut self, min: usize, text: B) -> Option<usize> {
+        if self.clients.len() < min {
+            return None;
+        }
+        Some(self.send_to_clients(text).await)
+    }
+
+    /// Send some text to the clients like [`send_to_clients`](#method.send_to_clients), returning a
+    /// stream of the ids of the clients as each of them is sent it. This can be used to show the
+    /// progress of a broadcast to a very large number of clients, or to stop part way through.
+    ///
+    /// Each client is sent the text like by `send_to_clients`, following the server's
+    /// [backpressure strategy](#method.with_backpressure) and [send
+    /// retries](#method.with_send_retries). Clients that turn out to have disconnected or that
+    /// skip the event aren't yielded, and the outcomes are recorded, removing the disconnected
+    /// clients, once the stream ends or is dropped. Dropping the stream early cancels the
+    /// broadcast: the clients that haven't been sent the text yet stay connected but never receive
+    /// it. As with `send_to_clients`, the text is added to the replay buffer as soon as the stream
+    /// is created.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use futures::StreamExt;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// drop(body);
+    ///
+    /// let progress = server.send_to_clients_with_progress(EventBuilder::new("Hi"));
+    /// let sent: Vec<_> = progress.collect().await;
+    /// assert_eq!(sent, vec![id]);
+    /// assert_eq!(server.connections(), 1);
+    /// # });
+    /// ```
+    pub fn send_to_clients_with_progress<B>(&mut self, text: B) -> SendProgress<'_, S>
+    where
+        B: Into<Bytes>,
+    {
+        if self.unsettled {
+            self.settle(true);
+        }
+        let mut bytes = text.into();
+        self.push_replay(bytes.slice(..));
+        self.stats.record_broadcast();
+        let (retries, mut backpressure) = (self.send_retries, self.backpressure);
+        // Like in `send_each`, end an event that was dropped before it was closed first.
+        if std::mem::take(&mut self.unterminated) {
+            let mut terminated = BytesMut::from("\n");
+            terminated.extend_from_slice(&bytes);
+            bytes = terminated.freeze();
+            backpressure = BackpressureStrategy::Block;
+        }
+        let sends = self
+            .clients
+            .iter()
+            .map(|_| Some(ClientSend::new(bytes.slice(..), retries, backpressure)))
+            .collect();
+        // If the stream is leaked rather than dropped, the next broadcast records the outcomes.
+        self.unsettled = true;
+        SendProgress {
+            pending: self.clients.len(),
+            server: self,
+            sends,
+            sent: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Send some text to the clients like [`send_to_clients`](#method.send_to_clients), but leave
+    /// the clients that couldn't be sent it in place instead of removing them. This lets the caller
+    /// decide when to give up on a client, such as after several failures in a row, and remove it
+    /// with [`remove_client`](#method.remove_client).
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    /// drop(body);
+    ///
+    /// let report = server.send_to_clients_no_prune(EventBuilder::new("Hello")).await;
+    /// assert_eq!(report.sent, 0);
+    /// assert_eq!(report.failed, vec![id]);
+    /// assert_eq!(server.connections(), 1);
+    /// # });
+    /// ```
+    pub async fn send_to_clients_no_prune<B: Into<Bytes>>(&mut self, text: B) -> SendReport {
+        let bytes = text.into();
+        self.push_replay(bytes.slice(..));
+        let report = self.send_each(|_| Some(bytes.slice(..)), false, None, self.backpressure).await;
+        self.sample_history();
+        report
+    }
+
+    /// Send some text to the clients, then wait up to `timeout` for each of their connections to
+    /// take it, such as by Hyper writing it to the socket. This is for callers that need to know
+    /// the event has left the server before going on.
+    ///
+    /// The clients that disconnected are removed like by `send_to_clients`, and reported as
+    /// failed. The clients that haven't taken the text by the timeout are reported as slow, but
+    /// are kept, as they may still catch up.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # use std::time::Duration;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, reading) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// let (client, _not_reading) = hyper::Body::channel();
+    /// let slow = server.add_client(client);
+    /// let reader = tokio::spawn(hyper::body::to_bytes(reading));
+    ///
+    /// let event = EventBuilder::new("Important");
+    /// let report = server.send_and_flush(event, Duration::from_millis(50)).await;
+    /// assert_eq!(report.sent, 2);
+    /// assert_eq!(report.slow, [slow]);
+    /// # drop(server);
+    /// # reader.await.unwrap().unwrap();
+    /// # }
+    /// ```
+    pub async fn send_and_flush<B>(&mut self, text: B, timeout: Duration) -> SendReport
+    where
+        B: Into<Bytes>,
+    {
+        let bytes = text.into();
+        self.push_replay(bytes.slice(..));
+        let mut report = self.send_each(|_| Some(bytes.slice(..)), true, None, self.backpressure).await;
+
+        // Every client left was sent the text, as the ones that failed have been removed.
+        let mut flushed = vec![false; self.clients.len()];
+        let flushes = self.clients.iter_mut().zip(&mut flushed).map(|(client, flushed)| async move {
+            let sender = &mut client.sender;
+            *flushed = future::poll_fn(|cx| sender.poll_ready(cx)).await.is_ok();
+        });
+        let _ = time::timeout(timeout, future::join_all(flushes)).await;
+        report.slow = self.clients
+            .iter()
+            .zip(flushed)
+            .filter(|(_, flushed)| !flushed)
+            .map(|(client, _)| client.meta.id)
+            .collect();
+        report
+    }
+
+    /// Add an event to the replay buffer if the server has one and the event has an id.
+    fn push_replay(&mut self, bytes: Bytes) {
+        if self.replay_capacity > 0 {
+            if let Some(id) = event_id(&bytes) {
+                self.expire_replay();
+                if self.replay.len() == self.replay_capacity {
+                    self.replay.pop_front();
+                }
+                self.replay.push_back((id.to_owned(), bytes, Instant::now()));
+            }
+        }
+    }
+
+    /// Drop the events that have outlived the replay TTL, if there is one, from the replay buffer.
+    fn expire_replay(&mut self) {
+        if let Some(ttl) = self.replay_ttl {
+            let now = Instant::now();
+            while self.replay.front().is_some_and(|(.., sent)| now - *sent >= ttl) {
+                self.replay.pop_front();
+            }
+        }
+    }
+
+    /// Send some text to every client of a user, and remove the ones that have disconnected.
+    ///
+    /// This function returns the number of the user's clients that are still connected.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (phone, phone_body) = hyper::Body::channel();
+    /// let (laptop, laptop_body) = hyper::Body::channel();
+    /// let (other, other_body) = hyper::Body::channel();
+    /// server.add_client_for_user("alice", phone);
+    /// server.add_client_for_user("alice", laptop);
+    /// server.add_client_for_user("bob", other);
+    ///
+    /// assert_eq!(server.send_to_user("alice", EventBuilder::new("New message")).await, 2);
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(phone_body).await.unwrap(), "data: New message\n\n");
+    /// assert_eq!(hyper::body::to_bytes(laptop_body).await.unwrap(), "data: New message\n\n");
+    /// assert_eq!(hyper::body::to_bytes(other_body).await.unwrap(), "");
+    /// # });
+    /// ```
+    pub async fn send_to_user<B: Into<Bytes>>(&mut self, user_id: &str, text: B) -> usize {
+        let of_user = |client: &Client<S>| client.meta.user_id.as_deref() == Some(user_id);
+        self.send_where(text.into(), of_user).await;
+        self.clients.iter().filter(|client| of_user(client)).count()
+    }
+
+    /// Send some text to a random `fraction` of the clients, such as `0.1` for 10% of them, and
+    /// remove the disconnected ones among those picked. This is useful for load tests and for
+    /// gradually rolling out a new event type.
+    ///
+    /// Every client is picked independently with a chance of `fraction`, so the number of clients
+    /// picked varies around `fraction` of them. They are picked again on every call, so a client
+    /// picked for one event won't necessarily be picked for the next; use
+    /// [`with_rng_seed`](#method.with_rng_seed) to pick the same clients every time in tests.
+    ///
+    /// This function returns the number of clients that were sent the text.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_rng_seed(1);
+    /// let mut bodies = Vec::new();
+    /// for _ in 0..100 {
+    ///     let (client, body) = hyper::Body::channel();
+    ///     server.add_client(client);
+    ///     bodies.push(body);
+    /// }
+    ///
+    /// let sent = server.send_to_fraction(0.1, EventBuilder::new("New feature")).await;
+    /// assert!(sent > 0 && sent < 30);
+    /// assert_eq!(server.send_to_fraction(0.0, EventBuilder::new("Nobody")).await, 0);
+    /// # });
+    /// ```
+    pub async fn send_to_fraction<B: Into<Bytes>>(&mut self, fraction: f64, text: B) -> usize {
+        let mut rng = self.rng;
+        let sent = self.send_where(text.into(), |_| next_random(&mut rng) < fraction).await;
+        self.rng = rng;
+        sent
+    }
+
+    /// Send some text to the clients in a bucket, and remove the ones that have disconnected.
+    /// Clients are put in buckets by
+    /// [`add_client_with_bucket_key`](#method.add_client_with_bucket_key).
+    ///
+    /// This function returns the number of the bucket's clients that are still connected.
+    pub async fn send_to_bucket<B: Into<Bytes>>(&mut self, bucket: u32, text: B) -> usize {
+        let in_bucket = |client: &Client<S>| client.meta.bucket == Some(bucket);
+        self.send_where(text.into(), in_bucket).await;
+        self.clients.iter().filter(|client| in_bucket(client)).count()
+    }
+
+    /// Send a sequence of events to the clients in order, giving each one its id. If the server
+    /// has a replay buffer every event is added to it, evicting the oldest events once it is full,
+    /// so this can also be used to seed the replay buffer before any clients connect.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new().with_replay_buffer(2);
+    /// server.broadcast_sequence(vec![
+    ///     ("1".to_owned(), EventBuilder::new("First")),
+    ///     ("2".to_owned(), EventBuilder::new("Second")),
+    ///     ("3".to_owned(), EventBuilder::new("Third")),
+    /// ]).await;
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client_with_last_id(client, Some("2"));
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "id: 3\ndata: Third\n\n");
+    /// # });
+    /// ```
+    pub async fn broadcast_sequence(
+        &mut self,
+        events: impl IntoIterator<Item = (String, OwnedEventBuilder)>,
+    ) -> usize {
+        for (id, event) in events {
+            self.send_to_clients(event.id(id)).await;
+        }
+        self.clients.len()
+    }
+
+    /// Send some text only to the clients subscribed to `topic` and the global clients, and remove
+    /// the ones that have disconnected. Events sent to a topic aren't kept in the replay buffer.
+    ///
+    /// This function returns the number of clients still subscribed to the topic, not counting
+    /// global clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (sports, sports_body) = hyper::Body::channel();
+    /// let (news, news_body) = hyper::Body::channel();
+    /// server.add_client_to_topic("sports", sports);
+    /// server.add_client_to_topic("news", news);
+    ///
+    /// assert_eq!(server.send_to_topic("sports", EventBuilder::new("Goal!")).await, 1);
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(sports_body).await.unwrap(), "data: Goal!\n\n");
+    /// assert_eq!(hyper::body::to_bytes(news_body).await.unwrap(), "");
+    /// # });
+    /// ```
+    pub async fn send_to_topic<B: Into<Bytes>>(&mut self, topic: &str, text: B) -> usize {
+        self.send_where(text.into(), |client| {
+            client.meta.global || client.meta.topic.as_deref() == Some(topic)
+        }).await;
+        self.connections_in_topic(topic)
+    }
+
+    /// Send some text to every topic at once, such as an announcement that the server is going
+    /// down for maintenance. Each client subscribed to a topic and each global client is sent the
+    /// text once, and the ones that have disconnected are removed. Clients that aren't subscribed
+    /// to a topic aren't sent it.
+    ///
+    /// This function returns the number of clients still subscribed to each topic, with the number
+    /// of global clients under [`GLOBAL_CLIENTS_KEY`](constant.GLOBAL_CLIENTS_KEY.html) if there
+    /// are any. Topics whose clients have all disconnected are left out.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{EventBuilder, GLOBAL_CLIENTS_KEY};
+    /// let mut server = hyper_usse::Server::new();
+    /// let (sports, _sports_body) = hyper::Body::channel();
+    /// server.add_client_to_topic("sports", sports);
+    /// let (news, news_body) = hyper::Body::channel();
+    /// server.add_client_to_topic("news", news);
+    /// let (monitor, _monitor_body) = hyper::Body::channel();
+    /// server.add_global_client(monitor);
+    /// drop(news_body);
+    ///
+    /// let report = server.broadcast_all_topics(EventBuilder::new("Maintenance soon")).await;
+    /// assert_eq!(report.len(), 2);
+    /// assert_eq!(report["sports"], 1);
+    /// assert_eq!(report[GLOBAL_CLIENTS_KEY], 1);
+    /// # });
+    /// ```
+    pub async fn broadcast_all_topics<B>(&mut self, text: B) -> HashMap<String, usize>
+    where
+        B: Into<Bytes>,
+    {
+        self.send_where(text.into(), |client| client.meta.global || client.meta.topic.is_some())
+            .await;
+        let mut report = HashMap::new();
+        for client in &self.clients {
+            let key = if client.meta.global {
+                GLOBAL_CLIENTS_KEY
+            } else if let Some(topic) = &client.meta.topic {
+                topic
+            } else {
+                continue;
+            };
+            *report.entry(key.to_owned()).or_insert(0) += 1;
+        }
+        report
+    }
+
+    /// Send each client the payload for the group `grouper` puts it in, such as its locale, in a
+    /// single pass over the clients. Clients whose group has no payload aren't sent anything, and
+    /// disconnected clients are removed from every group.
+    ///
+    /// This function returns the number of clients that were sent a payload.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use std::collections::HashMap;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (english, english_body) = hyper::Body::channel();
+    /// let (french, french_body) = hyper::Body::channel();
+    /// server.add_labeled_client("en", english);
+    /// server.add_labeled_client("fr", french);
+    ///
+    /// let mut payloads = HashMap::new();
+    /// payloads.insert("en".to_owned(), EventBuilder::new("Hello").into());
+    /// payloads.insert("fr".to_owned(), EventBuilder::new("Bonjour").into());
+    /// let sent = server.send_grouped(|client| client.label.clone().unwrap(), payloads).await;
+    /// assert_eq!(sent, 2);
+    ///
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(english_body).await.unwrap(), "data: Hello\n\n");
+    /// assert_eq!(hyper::body::to_bytes(french_body).await.unwrap(), "data: Bonjour\n\n");
+    /// # });
+    /// ```
+    pub async fn send_grouped<K, F>(&mut self, mut grouper: F, payloads: HashMap<K, Bytes>) -> usize
+    where
+        K: Eq + Hash,
+        F: FnMut(&ClientMeta) -> K,
+    {
+        let payload = |client: &Client<S>| payloads.get(&grouper(&client.meta)).cloned();
+        self.send_each(payload, true, None, self.backpressure).await.sent
+    }
+
+    /// Send a heartbeat only to the clients that haven't been sent anything for at least
+    /// `idle_threshold`, or that have been connected that long without being sent anything. Clients
+    /// that are regularly sent events don't need keep-alives, so this saves bandwidth on servers
+    /// where only some clients are busy.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn send_heartbeat_to_idle(&mut self, idle_threshold: Duration) -> usize {
+        let now = Instant::now();
+        let http1_only = self.http1_heartbeats_only;
+        self.send_where(self.heartbeat.slice(..), |client| {
+            let last_active = client.meta.last_sent.unwrap_or(client.meta.connected_at);
+            now.duration_since(last_active) >= idle_threshold && wants_heartbeat(client, http1_only)
+        }).await;
+        self.clients.len()
+    }
+
+    /// Send some bytes to the clients for which `selected` returns `true`, and remove the ones
+    /// that have disconnected.
+    ///
+    /// This function returns the number of clients that were sent the bytes.
+    async fn send_where(&mut self, bytes: Bytes, mut selected: impl FnMut(&Client<S>) -> bool) -> usize {
+        let payload = |client: &Client<S>| if selected(client) { Some(bytes.slice(..)) } else { None };
+        self.send_each(payload, true, None, self.backpressure).await.sent
+    }
+
+    /// Send part of an event to the clients, such as a line of an open event, and remove the ones
+    /// that have disconnected. Every client is waited for whatever the backpressure strategy is,
+    /// as skipping one part would leave the client with a broken event.
+    ///
+    /// This function returns the number of currently connected clients.
+    async fn send_partial<B: Into<Bytes>>(&mut self, frame: B) -> usize {
+        let bytes = frame.into();
+        self.send_each(|_| Some(bytes.slice(..)), true, None, BackpressureStrategy::Block).await;
+        self.sample_history();
+        self.clients.len()
+    }
+
+    /// Send each client the bytes `payload` returns for it, if any, and remove the ones that have
+    /// disconnected if `prune` is set. At most `concurrency` clients are sent to at once if it is
+    /// set, and all of them otherwise. Clients that aren't ready are treated according to
+    /// `backpressure`. This counts as a single broadcast in the server's stats.
+    async fn send_each(
+        &mut self,
+        mut payload: impl FnMut(&Client<S>) -> Option<Bytes>,
+        prune: bool,
+        concurrency: Option<usize>,
+        backpressure: BackpressureStrategy,
+    ) -> SendReport {
+        if self.unsettled {
+            self.settle(true);
+        }
+        // Every client has to be sent the end of a dropped event, whether or not it is sent
+        // anything else.
+        let terminate = std::mem::take(&mut self.unterminated);
+        let backpressure = if terminate { BackpressureStrategy::Block } else { backpressure };
+        let mut payload = |client: &Client<S>| match (payload(client), terminate) {
+            (Some(bytes), true) => {
+                let mut terminated = BytesMut::from("\n");
+                terminated.extend_from_slice(&bytes);
+                Some(terminated.freeze())
+            }
+            (None, true) => Some(Bytes::from_static(b"\n")),
+            (bytes, false) => bytes,
+        };
+        let retries = self.send_retries;
+        self.stats.record_broadcast();
+
+        // Each send records its own outcome in its client, so that if this future is dropped the
+        // outcomes of the sends that finished can still be recorded by the next broadcast. The
+        // sends are made as they are polled, so that only `concurrency` of them exist at once.
+        self.unsettled = true;
+        let concurrency = concurrency.unwrap_or(usize::MAX);
+        let mut clients = self.clients.iter_mut();
+        let mut sends = FuturesUnordered::new();
+        loop {
+            while sends.len() < concurrency {
+                let client = match clients.next() {
+                    Some(client) => client,
+                    None => break,
+                };
+                let bytes = payload(client);
+                sends.push(client.send(bytes, retries, backpressure));
+            }
+            if sends.next().await.is_none() {
+                break;
+            }
+        }
+        drop(sends);
+        self.settle(prune)
+    }
+
+    /// Record the outcomes of the sends of the last broadcast, and remove the clients that couldn't
+    /// be sent to if `prune` is set.
+    fn settle(&mut self, prune: bool) -> SendReport {
+        self.unsettled = false;
+        let now = Instant::now();
+        let mut received = 0;
+        let mut bytes_sent = 0u64;
+        let mut failed = Vec::new();
+        let mut failures = Vec::with_capacity(self.clients.len());
+        for client in &mut self.clients {
+            let outcome = client.outcome.take();
+            failures.push(matches!(outcome, Some((_, false))));
+            match outcome {
+                Some((bytes, true)) => {
+                    received += 1;
+                    bytes_sent = bytes_sent.saturating_add(bytes.len() as u64);
+                    client.sent(&bytes, now, self.track_acks, &mut self.resume_points);
+                }
+                Some((_, false)) => failed.push(client.meta.id),
+                None => {}
+            }
+        }
+        if prune {
+            let mut failures = failures.into_iter();
+            self.remove_clients(|_| failures.next().unwrap(), false);
+        }
+
+        self.stats.record_bytes_sent(bytes_sent);
+        SendReport { sent: received, failed, slow: Vec::new() }
+    }
+
+    /// Send a single client a `retry` directive, telling it to wait `ms` milliseconds before
+    /// reconnecting if its connection is lost, such as a longer backoff for a client on a poor
+    /// network. The directive doesn't fire an event. A client that has disconnected is removed.
+    ///
+    /// This function returns `false` if the client couldn't be sent the directive or had already
+    /// been removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    ///
+    /// assert!(server.send_retry_to_client(id, 10_000).await);
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "retry: 10000\n\n");
+    /// # });
+    /// ```
+    pub async fn send_retry_to_client(&mut self, id: ClientId, ms: u64) -> bool {
+        let retries = self.send_retries;
+        let client = match self.clients.iter_mut().find(|client| client.meta.id == id) {
+            Some(client) => client,
+            None => return false,
+        };
+        let bytes = Bytes::from(format!("retry: {}\n\n", ms));
+        if !send_with_retries(&mut client.sender, bytes.slice(..), retries).await {
+            self.remove_clients(|client| client.meta.id == id, false);
+            return false;
+        }
+        client.sent(&bytes, Instant::now(), self.track_acks, &mut self.resume_points);
+        true
+    }
+
+    /// Make the next event broadcast to a client tell it to wait `ms` milliseconds before
+    /// reconnecting, like [`send_retry_to_client`](#method.send_retry_to_client) but without
+    /// sending anything until then. Setting it again before the next event replaces the time.
+    ///
+    /// This function returns `false` if the client has been removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    ///
+    /// assert!(server.set_default_retry_for(id, 10_000));
+    /// server.send_to_clients(EventBuilder::new("Hello")).await;
+    /// drop(server);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "retry: 10000\n\ndata: Hello\n\n");
+    /// # });
+    /// ```
+    pub fn set_default_retry_for(&mut self, id: ClientId, ms: u64) -> bool {
+        match self.clients.iter_mut().find(|client| client.meta.id == id) {
+            Some(client) => {
+                client.retry = Some(ms);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether a single client is still connected, without sending it anything. A client
+    /// whose connection has closed is removed.
+    ///
+    /// Clients that are connected but can't currently accept more data count as connected. This
+    /// function returns `false` if the client was removed or had already been removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    /// assert!(server.probe(id).await);
+    ///
+    /// drop(body);
+    /// assert!(!server.probe(id).await);
+    /// assert_eq!(server.connections(), 0);
+    /// # });
+    /// ```
+    pub async fn probe(&mut self, id: ClientId) -> bool {
+        let client = match self.clients.iter_mut().find(|client| client.meta.id == id) {
+            Some(client) => client,
+            None => return false,
+        };
+        let sender = &mut client.sender;
+        if future::poll_fn(|cx| Poll::Ready(readiness(sender, cx))).await == Readiness::Closed {
+            self.remove_clients(|client| client.meta.id == id, false);
+            return false;
+        }
+        true
+    }
+
+    /// Send an event that has already been built to the clients, without building it again. This
+    /// is the same as `send_to_clients(cached)`.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn broadcast_cached(&mut self, cached: &CachedEvent) -> usize {
+        self.send_to_clients(cached).await
+    }
+
+    /// Send an event to the clients. This is the same as `send_to_clients(event)`, but the event is
+    /// built into a buffer owned by the server; once the clients have been sent an event its memory
+    /// is reused, so frequent broadcasts don't allocate for every event.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn broadcast_event(&mut self, event: &EventBuilder<'_>) -> usize {
+        let mut event = Cow::Borrowed(event);
+        if let Some(event_type) = self.prefixed_event_type(event.event_type.as_deref()) {
+            event.to_mut().event_type = Some(Cow::Owned(event_type));
+        }
+        event.write_to(&mut self.scratch);
+        let bytes = self.scratch.split().freeze();
+        self.send_to_clients(bytes).await
+    }
+
+    /// Build a payload with `f` and send it to the clients, catching any panic while building it.
+    /// This keeps a buggy event source from unwinding through the code that holds the server,
+    /// which would poison a `std::sync::Mutex` around it.
+    ///
+    /// Only building the payload is guarded. Nothing has been sent when `f` runs, so a panic
+    /// leaves the server as it was. `f` must be
+    /// [`UnwindSafe`](https://doc.rust-lang.org/std/panic/trait.UnwindSafe.html): anything it
+    /// borrows mutably could be left half updated by the panic, so it has to be wrapped in
+    /// `AssertUnwindSafe` to say that is fine. Panics are still printed by the panic hook, and
+    /// aren't caught at all if the program is built with `panic = "abort"`.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Errors
+    /// Fails with the panic's payload if `f` panicked.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let result = server.try_broadcast(|| panic!("buggy event source")).await;
+    /// assert_eq!(*result.unwrap_err().downcast::<&str>().unwrap(), "buggy event source");
+    ///
+    /// assert_eq!(server.try_broadcast(|| EventBuilder::new("Fine").into()).await.unwrap(), 0);
+    /// # });
+    /// ```
+    pub async fn try_broadcast<F>(&mut self, f: F) -> Result<usize, Box<dyn Any + Send>>
+    where
+        F: FnOnce() -> Bytes + UnwindSafe,
+    {
+        let bytes = panic::catch_unwind(f)?;
+        Ok(self.send_to_clients(bytes).await)
+    }
+
+    /// Send a value to the clients as the event it turns itself into with
+    /// [`ToEvent`](trait.ToEvent.html).
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn broadcast<T: ToEvent + ?Sized>(&mut self, value: &T) -> usize {
+        self.broadcast_event(&value.to_event()).await
+    }
+
+    /// Get what an event type should be sent as with the server's event type prefix, or `None` if
+    /// it should be sent as it is.
+    fn prefixed_event_type(&self, event_type: Option<&str>) -> Option<String> {
+        let prefix = self.event_type_prefix.as_deref()?;
+        let event_type = event_type?;
+        Some(match event_type.strip_prefix('.') {
+            Some(absolute) => absolute.to_owned(),
+            None => format!("{}.{}", prefix, event_type),
+        })
+    }
+
+    /// Send an event to the clients like [`broadcast_event`](#method.broadcast_event), but first
+    /// check it with [`EventBuilder::validate`](struct.EventBuilder.html#method.validate), or
+    /// `validate_strict` if the server was configured
+    /// [`with_strict_validation`](#method.with_strict_validation), and against the server's maximum
+    /// event size, if one was set with [`with_max_event_size`](#method.with_max_event_size).
+    /// Nothing is sent if the event is invalid.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Errors
+    /// Fails if the event is invalid or too large.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{EventBuilder, EventError};
+    /// let mut server = hyper_usse::Server::new().with_max_event_size(64);
+    /// let event = EventBuilder::new("Data").event_type("bad\ntype");
+    /// assert_eq!(server.broadcast_checked(&event).await, Err(EventError::InvalidEventType));
+    ///
+    /// let event = EventBuilder::new("x".repeat(64));
+    /// assert_eq!(server.broadcast_checked(&event).await, Err(EventError::TooLarge));
+    ///
+    /// assert_eq!(server.broadcast_checked(&EventBuilder::new("Data")).await, Ok(0));
+    /// # });
+    /// ```
+    pub async fn broadcast_checked(&mut self, event: &EventBuilder<'_>) -> Result<usize, EventError> {
+        if self.strict {
+            event.validate_strict()?;
+        } else {
+            event.validate()?;
+        }
+        if self.max_event_size.is_some_and(|max| event.encoded_len() > max) {
+            return Err(EventError::TooLarge);
+        }
+        Ok(self.broadcast_event(event).await)
+    }
+
+    /// Send an event containing just `data` to the clients, with the server's default event type
+    /// if one was set with [`with_default_event_type`](#method.with_default_event_type).
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new().with_default_event_type("message");
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.send_data_to_clients("Hello").await;
+    /// drop(server);
+    /// let received = hyper::body::to_bytes(body).await.unwrap();
+    /// assert_eq!(received, "event: message\ndata: Hello\n\n");
+    /// # });
+    /// ```
+    pub async fn send_data_to_clients(&mut self, data: &str) -> usize {
+        let id = if self.timestamp_ids {
+            Some(self.next_timestamp_id())
+        } else {
+            None
+        };
+        let default_event_type = self.default_event_type.as_deref();
+        let mut event = EventBuilder::new(data);
+        event.event_type = self
+            .prefixed_event_type(default_event_type)
+            .map(Cow::Owned)
+            .or_else(|| default_event_type.map(Cow::Borrowed));
+        event.id = id.map(Cow::Owned);
+        let bytes: Bytes = event.into();
+        self.send_to_clients(bytes).await
+    }
+
+    /// Send an event containing `data` to the clients, with the event type `event_type` if one is
+    /// given, and an id made from the current time.
+    ///
+    /// The id is the number of milliseconds since the Unix epoch, so ids are meaningful and sort
+    /// in the order the events were sent, even across restarts of the server. When several events
+    /// are sent in the same millisecond, the later ones get a counter after a dash, such as
+    /// `1700000000000-1`. Ids are compared as numbers and then by counter, not as strings.
+    ///
+    /// The ids are only as accurate as the system clock. If the clock goes back while the server
+    /// is running, the server keeps counting from the last millisecond it used, so ids never go
+    /// backwards; but a restarted server, or several servers with clocks that disagree, can give
+    /// out ids that are out of order or clash.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.send_timestamped("Hello", Some("greeting")).await;
+    /// drop(server);
+    /// let received = hyper::body::to_bytes(body).await.unwrap();
+    /// let received = std::str::from_utf8(&received).unwrap();
+    /// assert!(received.starts_with("id: 1"));
+    /// assert!(received.ends_with("\nevent: greeting\ndata: Hello\n\n"));
+    /// # });
+    /// ```
+    pub async fn send_timestamped(&mut self, data: &str, event_type: Option<&str>) -> usize {
+        let id = self.next_timestamp_id();
+        let mut event = EventBuilder::new(data).id(id);
+        event.event_type = self
+            .prefixed_event_type(event_type)
+            .map(Cow::Owned)
+            .or_else(|| event_type.map(Cow::Borrowed));
+        self.send_to_clients(event).await
+    }
+
+    /// Send an event containing `data` to the clients, with the event type `event_type` if one is
+    /// given, and an id made by hashing the data. Identical data always gets the same id, so
+    /// clients can tell that an event is one they have already seen, such as after reconnecting.
+    ///
+    /// The id is the 64-bit hash of the data written as 16 hexadecimal digits. The hash is FNV-1a
+    /// unless another was set with [`with_content_hash`](#method.with_content_hash). Different data
+    /// can hash to the same id, which would make a client wrongly treat an event as a duplicate;
+    /// with a 64-bit hash this is unlikely, but FNV-1a isn't made to resist data chosen to collide,
+    /// so use a cryptographic hash if clients can choose the data.
+    ///
+    /// Repeated data is kept in the replay buffer once for each time it was sent. A client that
+    /// reconnects with an id the buffer holds more than once is replayed everything after the
+    /// oldest of them, so it can be sent the repeats again; it can drop them by their ids.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.broadcast_with_content_id("Hello", None).await;
+    /// drop(server);
+    /// let received = hyper::body::to_bytes(body).await.unwrap();
+    /// assert_eq!(received, "id: 63f0bfacf2c00f6b\ndata: Hello\n\n");
+    /// # });
+    /// ```
+    pub async fn broadcast_with_content_id(
+        &mut self,
+        data: &str,
+        event_type: Option<&str>,
+    ) -> usize {
+        let id = format!("{:016x}", (self.content_hash)(data.as_bytes()));
+        let mut event = EventBuilder::new(data).id(id);
+        event.event_type = self
+            .prefixed_event_type(event_type)
+            .map(Cow::Owned)
+            .or_else(|| event_type.map(Cow::Borrowed));
+        self.send_to_clients(event).await
+    }
+
+    /// Get the next timestamp id, which is never before the last one.
+    fn next_timestamp_id(&mut self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        let (last, count) = &mut self.last_timestamp;
+        if now > *last {
+            *last = now;
+            *count = 0;
+            now.to_string()
+        } else {
+            *count += 1;
+            format!("{}-{}", last, count)
+        }
+    }
+
+    /// Send every line read from `reader` to the clients as its own event, with the event type
+    /// `event_type` if one is given, until the end of the reader. This can be used to tail a log
+    /// file to a browser. A last line without a line break is sent too, and disconnected clients
+    /// are removed as each event is sent.
+    ///
+    /// This function returns the number of events that were sent.
+    ///
+    /// # Errors
+    /// Fails if reading fails, or if a line isn't valid UTF-8. The lines before it will have been
+    /// sent.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::BackpressureStrategy;
+    /// let mut server = hyper_usse::Server::new().with_backpressure(BackpressureStrategy::Block);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// let log: &[u8] = b"started\r\nlistening";
+    /// let producer = async move { server.stream_reader(log, Some("log")).await.unwrap() };
+    /// let (sent, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(sent, 2);
+    /// assert_eq!(
+    ///     received.unwrap(),
+    ///     "event: log\ndata: started\n\nevent: log\ndata: listening\n\n",
+    /// );
+    /// # });
+    /// ```
+    pub async fn stream_reader<R>(
+        &mut self,
+        mut reader: R,
+        event_type: Option<&str>,
+    ) -> std::io::Result<usize>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut line = String::new();
+        let mut sent = 0;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(sent);
+            }
+            let text = line.strip_suffix('\n').unwrap_or(&line);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            // An empty line is sent as an event with empty data, as empty data on its own isn't
+            // dispatched.
+            let mut event = EventBuilder::new(if text.is_empty() { "\n" } else { text });
+            event.event_type = event_type.map(Cow::Borrowed);
+            self.send_to_clients(event).await;
+            sent += 1;
+        }
+    }
+
+    /// Send an event to the clients with one `data:` line for each of `lines`, without joining
+    /// them into a single string first. Line breaks within one of the lines split it into several
+    /// `data:` lines, so they can't break the framing.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.send_multiline_to_clients(&["first", "", "third"], Some("1"), None).await;
+    /// drop(server);
+    /// let received = hyper::body::to_bytes(body).await.unwrap();
+    /// assert_eq!(received, "id: 1\ndata: first\ndata: \ndata: third\n\n");
+    /// # });
+    /// ```
+    pub async fn send_multiline_to_clients(
+        &mut self,
+        lines: &[&str],
+        id: Option<&str>,
+        event_type: Option<&str>,
+    ) -> usize {
+        let mut event = String::new();
+        if let Some(id) = id {
+            event.push_str(ID_FIELD);
+            push_single_line(&mut event, id);
+            event.push('\n');
+        }
+        if let Some(event_type) = event_type {
+            event.push_str(EVENT_FIELD);
+            push_single_line(&mut event, event_type);
+            event.push('\n');
+        }
+        for &line in lines {
+            let mut parts = crate::frame::lines(line).peekable();
+            if parts.peek().is_none() {
+                event.push_str(DATA_FIELD);
+                event.push('\n');
+            }
+            for part in parts {
+                event.push_str(DATA_FIELD);
+                event.push_str(part);
+                event.push('\n');
+            }
+        }
+        event.push('\n');
+        self.send_to_clients(event).await
+    }
+
+    /// Send a heartbeat (empty SSE) to all clients. This does not perform any action, but will
+    /// prevent your connection being timed out for lasting too long without any data being sent.
+    /// The heartbeat is an empty comment unless another one was set with
+    /// [`with_heartbeat_event`](#method.with_heartbeat_event), and is only sent to HTTP/1 clients
+    /// if the server was configured
+    /// [`with_http1_heartbeats_only`](#method.with_http1_heartbeats_only).
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn send_heartbeat(&mut self) -> usize {
+        let heartbeat = self.heartbeat.slice(..);
+        if !self.http1_heartbeats_only {
+            return self.send_to_clients(heartbeat).await;
+        }
+        self.send_where(heartbeat, |client| wants_heartbeat(client, true)).await;
+        self.clients.len()
+    }
+
+    /// Open an event whose data will be streamed to the clients line by line, instead of being
+    /// built up front. The `id` and `event_type` fields, if any, are sent immediately, with any
+    /// line breaks in them left out like by [EventBuilder](struct.EventBuilder.html).
+    ///
+    /// Push data with [`OpenEvent::send_data`](struct.OpenEvent.html#method.send_data) and finish
+    /// the event with [`OpenEvent::close`](struct.OpenEvent.html#method.close). Clients only
+    /// dispatch the event once it is closed, so a slow producer holds the event open on the
+    /// client side until then.
+    ///
+    /// Every part of the event is sent to every client whatever the server's
+    /// [backpressure strategy](#method.with_backpressure), so that no client is sent part of it, and
+    /// the event isn't added to the replay buffer. If the event is dropped without being closed,
+    /// such as by cancelling a send, the next broadcast first sends the blank line that ends it,
+    /// so that it doesn't become part of the dropped event; the clients then dispatch what they
+    /// were sent of the dropped event.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// let producer = async move {
+    ///     let mut event = server.open_event(None, Some("log")).await;
+    ///     event.send_data("first line").await;
+    ///     event.send_data("second line").await;
+    ///     event.close().await;
+    /// };
+    /// let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(received.unwrap(), "event: log\ndata: first line\ndata: second line\n\n");
+    /// # });
+    /// ```
+    pub async fn open_event(&mut self, id: Option<&str>, event_type: Option<&str>) -> OpenEvent<'_, S> {
+        let mut header = String::new();
+        if let Some(id) = id {
+            header.push_str(ID_FIELD);
+            push_single_line(&mut header, id);
+            header.push('\n');
+        }
+        if let Some(event_type) = event_type {
+            header.push_str(EVENT_FIELD);
+            push_single_line(&mut header, event_type);
+            header.push('\n');
+        }
+        // The event is created first so that if sending the header is cancelled, it still
+        // terminates the event when dropped.
+        let event = OpenEvent { server: self, closed: false };
+        if !header.is_empty() {
+            event.server.send_partial(header).await;
+        }
+        event
+    }
+
+    /// Send a large event to the clients in chunks of `chunk_lines` data lines, so that the
+    /// transport can write each chunk as it is sent instead of buffering one huge write. The
+    /// chunks are all part of the same event: the clients only dispatch it once the blank line
+    /// ending it is sent after the last chunk. Disconnected clients are removed as each chunk is
+    /// sent. Like with [`open_event`](#method.open_event), every chunk is sent to every client
+    /// whatever the backpressure strategy.
+    ///
+    /// This function returns the number of currently connected clients.
+    ///
+    /// # Panics
+    /// Panics if `chunk_lines` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// let producer = async move { server.broadcast_chunked("one\ntwo\nthree", 2).await };
+    /// let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(received.unwrap(), "data: one\ndata: two\ndata: three\n\n");
+    /// # });
+    /// ```
+    pub async fn broadcast_chunked(&mut self, data: &str, chunk_lines: usize) -> usize {
+        assert!(chunk_lines > 0, "chunks must have at least 1 line");
+        // Like `EventBuilder`, leave out a leading byte order mark, which clients would strip.
+        let data = data.strip_prefix('\u{FEFF}').unwrap_or(data);
+        let lines: Vec<&str> = lines(data).collect();
+        let event = self.open_event(None, None).await;
+        for chunk in lines.chunks(chunk_lines) {
+            let mut frame = String::with_capacity(chunk.iter().map(|line| 7 + line.len()).sum());
+            for line in chunk {
+                frame.push_str(DATA_FIELD);
+                frame.push_str(line);
+                frame.push('\n');
+            }
+            event.server.send_partial(frame).await;
+        }
+        event.close().await
+    }
+
+    /// Move the server into a background task, and get a handle through which it can be used.
+    ///
+    /// This is an alternative to sharing the server in an `Arc<Mutex<Server>>`: the handle can be
+    /// cloned and used from anywhere, and the task serializes access to the server. The task stops
+    /// and drops the server (ending the clients' streams) once every handle has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let server = hyper_usse::Server::new().spawn();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client).await;
+    ///
+    /// assert_eq!(server.broadcast(hyper_usse::EventBuilder::new("Hello")).await, 1);
+    /// server.disconnect_all().await;
+    /// assert_eq!(server.connections().await, 0);
+    /// # }
+    /// ```
+    pub fn spawn(mut self) -> ServerHandle<S>
+    where
+        S: Send + 'static,
+    {
+        let (commands, mut receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                match command {
+                    Command::AddClient(client, reply) => {
+                        let _ = reply.send(self.add_client(client));
+                    }
+                    Command::Broadcast(bytes, reply) => {
+                        let _ = reply.send(self.send_to_clients(bytes).await);
+                    }
+                    Command::Connections(reply) => {
+                        let _ = reply.send(self.connections());
+                    }
+                    Command::DisconnectAll(reply) => {
+                        self.disconnect_all();
+                        let _ = reply.send(());
+                    }
+                    Command::RemoveClient(id) => {
+                        self.remove_client(id);
+                    }
+                }
+            }
+        });
+        ServerHandle { commands }
+    }
+
+    /// Stage an event to be sent by the next [`flush_staged`](#method.flush_staged), replacing
+    /// any event already staged with the same key. This collapses rapid updates to the same piece
+    /// of state into a single event, so clients are only sent its latest value.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::{BackpressureStrategy, EventBuilder};
+    /// let mut server = hyper_usse::Server::new().with_backpressure(BackpressureStrategy::Block);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// server.stage_update("price", EventBuilder::new("100"));
+    /// server.stage_update("volume", EventBuilder::new("5"));
+    /// server.stage_update("price", EventBuilder::new("101"));
+    ///
+    /// let producer = async move {
+    ///     server.flush_staged().await;
+    /// };
+    /// let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    /// assert_eq!(received.unwrap(), "data: 101\n\ndata: 5\n\n");
+    /// # });
+    /// ```
+    pub fn stage_update<B: Into<Bytes>>(&mut self, key: impl Into<String>, event: B) {
+        let key = key.into();
+        let event = event.into();
+        match self.staged.iter_mut().find(|(staged, _)| *staged == key) {
+            Some((_, staged)) => *staged = event,
+            None => self.staged.push((key, event)),
+        }
+    }
+
+    /// Send every staged event to the clients, in the order their keys were first staged, and
+    /// clear them.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn flush_staged(&mut self) -> usize {
+        for (_, event) in std::mem::take(&mut self.staged) {
+            self.send_to_clients(event).await;
+        }
+        self.clients.len()
+    }
+
+    /// Spawn a task that calls [`flush_staged`](#method.flush_staged) on `server` every
+    /// `period`, so that staged updates are sent regularly. The task stops once every other
+    /// reference to the server has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use tokio::sync::Mutex;
+    /// use hyper_usse::{EventBuilder, Server};
+    ///
+    /// let server = Arc::new(Mutex::new(Server::new().with_connection_history(10)));
+    /// let flusher = Server::flush_staged_every(&server, Duration::from_millis(10));
+    /// server.lock().await.stage_update("price", EventBuilder::new("100"));
+    ///
+    /// tokio::time::delay_for(Duration::from_millis(50)).await;
+    /// assert_eq!(server.lock().await.connection_history().len(), 1);
+    ///
+    /// drop(server);
+    /// flusher.await.unwrap();
+    /// # }
+    /// ```
+    pub fn flush_staged_every(server: &Arc<Mutex<Self>>, period: Duration) -> JoinHandle<()>
+    where
+        S: Send + 'static,
+    {
+        let server = Arc::downgrade(server);
+        tokio::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                interval.tick().await;
+                let server = match server.upgrade() {
+                    Some(server) => server,
+                    None => break,
+                };
+                let mut server = server.lock().await;
+                if !server.staged.is_empty() {
+                    server.flush_staged().await;
+                }
+            }
+        })
+    }
+
+    /// Stage a payload to be broadcast by the task spawned with
+    /// [`broadcast_debounced`](#method.broadcast_debounced) once no other payload has been staged
+    /// for a while, replacing any payload already staged. Nothing is sent if there is no such
+    /// task.
+    pub fn stage<B: Into<Bytes>>(&mut self, text: B) {
+        self.debounced = Some((text.into(), Instant::now()));
+        self.debounce_staged.notify();
+    }
+
+    /// Spawn a task that debounces the payloads given to [`stage`](#method.stage) on `server`: once
+    /// `delay` has passed without another payload being staged, the latest one is broadcast. This
+    /// is for sources that fire in bursts where only the final state matters, such as a slider
+    /// being dragged, so that clients are sent one event per burst instead of every step.
+    ///
+    /// The payload is sent on the trailing edge only. The first payload of a burst isn't sent
+    /// straight away, so every event is delayed by at least `delay`, and a source that never
+    /// pauses for `delay` is never sent. The task stops once every other reference to the server
+    /// has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use tokio::sync::Mutex;
+    /// use hyper_usse::{EventBuilder, Server};
+    ///
+    /// let server = Arc::new(Mutex::new(Server::new()));
+    /// let (client, body) = hyper::Body::channel();
+    /// server.lock().await.add_client(client);
+    /// let debouncer = Server::broadcast_debounced(&server, Duration::from_millis(20));
+    ///
+    /// for position in 0..10 {
+    ///     server.lock().await.stage(EventBuilder::new(format!("slider at {}", position)));
+    /// }
+    /// tokio::time::delay_for(Duration::from_millis(100)).await;
+    ///
+    /// drop(server);
+    /// debouncer.await.unwrap();
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "data: slider at 9\n\n");
+    /// # }
+    /// ```
+    pub fn broadcast_debounced(server: &Arc<Mutex<Self>>, delay: Duration) -> JoinHandle<()>
+    where
+        S: Send + 'static,
+    {
+        let server = Arc::downgrade(server);
+        tokio::spawn(async move {
+            let staged = match server.upgrade() {
+                Some(server) => Arc::clone(&server.lock().await.debounce_staged),
+                None => return,
+            };
+            loop {
+                // Also wake up regularly to notice the server being dropped.
+                let _ = time::timeout(delay, staged.notified()).await;
+                let server = match server.upgrade() {
+                    Some(server) => server,
+                    None => break,
+                };
+                loop {
+                    let mut server = server.lock().await;
+                    let settled = match &server.debounced {
+                        Some((_, staged_at)) => *staged_at + delay,
+                        None => break,
+                    };
+                    let now = Instant::now();
+                    if now >= settled {
+                        let (payload, _) = server.debounced.take().unwrap();
+                        server.send_to_clients(payload).await;
+                        break;
+                    }
+                    drop(server);
+                    time::delay_for(settled - now).await;
+                }
+            }
+        })
+    }
+
+    /// Spawn a task that calls [`send_heartbeat`](#method.send_heartbeat) on `server` every
+    /// `period`, to keep the clients' connections from timing out. See
+    /// [HeartbeatHandle](struct.HeartbeatHandle.html) for when the task stops.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use hyper::body::HttpBody;
+    /// use hyper_usse::Server;
+    ///
+    /// let server = Arc::new(tokio::sync::Mutex::new(Server::new()));
+    /// let (client, mut body) = hyper::Body::channel();
+    /// server.lock().await.add_client(client);
+    ///
+    /// let heartbeat = Server::spawn_heartbeat(&server, Duration::from_millis(10));
+    /// assert_eq!(body.data().await.unwrap().unwrap(), ":\n\n");
+    /// heartbeat.stop().await;
+    /// # }
+    /// ```
+    pub fn spawn_heartbeat(server: &Arc<Mutex<Self>>, period: Duration) -> HeartbeatHandle
+    where
+        S: Send + 'static,
+    {
+        let server = Arc::downgrade(server);
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                // The handle being dropped stops the task too.
+                if let future::Either::Right(_) =
+                    future::select(Box::pin(interval.tick()), &mut stopped).await
+                {
+                    break;
+                }
+                let server = match server.upgrade() {
+                    Some(server) => server,
+                    None => break,
+                };
+                server.lock().await.send_heartbeat().await;
+            }
+        });
+        HeartbeatHandle { stop, task }
+    }
+
+    /// Spawn a task that forwards every message received from a Tokio broadcast channel to the
+    /// clients of `server`. This connects an application's existing event bus to its SSE clients.
+    ///
+    /// If the task falls behind and the channel drops messages, the clients are sent a
+    /// `: missed N events` comment when `notify_lagged` is set. The task ends once every sender
+    /// of the channel has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use std::sync::Arc;
+    /// use tokio::sync::{broadcast, Mutex};
+    /// use hyper_usse::{EventBuilder, Server};
+    ///
+    /// let server = Arc::new(Mutex::new(Server::new()));
+    /// let (events, receiver) = broadcast::channel::<EventBuilder<'static>>(16);
+    /// let pump = Server::pump_broadcast(Arc::clone(&server), receiver, true);
+    ///
+    /// events.send(EventBuilder::new("Hello")).unwrap();
+    /// drop(events);
+    /// pump.await.unwrap();
+    /// # }
+    /// ```
+    pub fn pump_broadcast<T>(
+        server: Arc<Mutex<Self>>,
+        mut events: broadcast::Receiver<T>,
+        notify_lagged: bool,
+    ) -> JoinHandle<()>
+    where
+        T: Into<Bytes> + Clone + Send + 'static,
+        S: Send + 'static,
+    {
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        server.lock().await.send_to_clients(event).await;
+                    }
+                    Err(broadcast::RecvError::Lagged(missed)) => {
+                        if notify_lagged {
+                            let comment = format!(": missed {} events\n\n", missed);
+                            server.lock().await.send_to_clients(comment).await;
+                        }
+                    }
+                    Err(broadcast::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Remove a single client, without aborting its connection like
+    /// [`disconnect_all`](#method.disconnect_all) does, so its stream ends cleanly.
+    ///
+    /// The stream ends without HTTP trailers; use
+    /// [`disconnect_client_with_trailers`](#method.disconnect_client_with_trailers) to send some.
+    ///
+    /// This function returns `false` if the client had already been removed.
+    pub fn remove_client(&mut self, id: ClientId) -> bool {
+        self.remove_clients(|client| client.meta.id == id, false) > 0
+    }
+
+    /// Remove a single client like [`remove_client`](#method.remove_client), ending its stream
+    /// with HTTP trailers, such as a final status for HTTP/2 clients that read them. The trailers
+    /// are sent with [`EventSink::send_trailers`](trait.EventSink.html#method.send_trailers).
+    ///
+    /// Hyper 0.13's body `Sender` can't send trailers, so with it this always fails with
+    /// [`SinkError::Unsupported`](enum.SinkError.html) and a final status has to be sent as an
+    /// event instead. Trailers wouldn't reach most clients anyway: HTTP/1.1 clients only see them
+    /// if they asked for them with `TE: trailers`, and `EventSource` ignores them on every
+    /// protocol.
+    ///
+    /// The client is removed even if the trailers couldn't be sent. This function returns
+    /// [`SinkError::Closed`](enum.SinkError.html) if the client had already been removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # use hyper_usse::SinkError;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// let id = server.add_client(client);
+    ///
+    /// let result = server.disconnect_client_with_trailers(id, hyper::HeaderMap::new());
+    /// assert_eq!(result, Err(SinkError::Unsupported));
+    /// assert_eq!(server.connections(), 0);
+    /// ```
+    pub fn disconnect_client_with_trailers(
+        &mut self,
+        id: ClientId,
+        trailers: HeaderMap,
+    ) -> Result<(), SinkError> {
+        let client = self
+            .clients
+            .iter_mut()
+            .find(|client| client.meta.id == id)
+            .ok_or(SinkError::Closed)?;
+        let result = client.sender.send_trailers(trailers);
+        self.remove_client(id);
+        result
+    }
+
+    /// Disconnect all clients that are currently connected to the server. Their connections are
+    /// aborted, so clients see an error; use [`clear`](#method.clear) to end them cleanly.
+    pub fn disconnect_all(&mut self) {
+        self.remove_clients(|_| true, true);
+    }
+
+    /// Disconnect every client of a user, such as when they log out. Their connections are aborted
+    /// like with [`disconnect_all`](#method.disconnect_all).
+    ///
+    /// This function returns the number of clients that were disconnected.
+    pub fn disconnect_user(&mut self, user_id: &str) -> usize {
+        self.remove_clients(|client| client.meta.user_id.as_deref() == Some(user_id), true)
+    }
+
+    /// Stop tracking every client, without aborting them like
+    /// [`disconnect_all`](#method.disconnect_all) does. The clients' bodies end normally once
+    /// anything already sent to them has been flushed, so they see a clean end of stream instead
+    /// of an error.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// server.send_to_clients(EventBuilder::new("Goodbye")).await;
+    ///
+    /// server.clear();
+    /// assert_eq!(server.connections(), 0);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "data: Goodbye\n\n");
+    /// # });
+    /// ```
+    pub fn clear(&mut self) {
+        self.remove_clients(|_| true, false);
+    }
+
+    /// Shut the server down gracefully: wait up to `deadline` for every client to take the data it
+    /// was already sent, end the streams of the ones that did, and abort the rest. This fits the
+    /// grace period an orchestrator gives a process before killing it.
+    ///
+    /// The server is borrowed for the whole shutdown, so nothing else can broadcast in the
+    /// meantime. Events held for clients by
+    /// [`BackpressureStrategy::DropOldest`](enum.BackpressureStrategy.html) are sent first. The
+    /// server has no clients afterwards, but can still be used.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # use std::time::Duration;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, reading) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// let (client, _stuck) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// let reader = tokio::spawn(hyper::body::to_bytes(reading));
+    ///
+    /// server.send_to_clients(EventBuilder::new("Shutting down")).await;
+    /// let report = server.graceful_shutdown(Duration::from_millis(50)).await;
+    /// assert_eq!((report.closed, report.aborted), (1, 1));
+    /// assert_eq!(reader.await.unwrap().unwrap(), "data: Shutting down\n\n");
+    /// # }
+    /// ```
+    pub async fn graceful_shutdown(&mut self, deadline: Duration) -> ShutdownReport {
+        if self.unsettled {
+            self.settle(false);
+        }
+        let mut flushed = vec![false; self.clients.len()];
+        let flushes = self.clients.iter_mut().zip(&mut flushed).map(|(client, flushed)| async move {
+            if let Some(held) = client.held.take() {
+                if client.sender.send_bytes(held).await.is_err() {
+                    return;
+                }
+            }
+            let sender = &mut client.sender;
+            *flushed = future::poll_fn(|cx| sender.poll_ready(cx)).await.is_ok();
+        });
+        let _ = time::timeout(deadline, future::join_all(flushes)).await;
+
+        let mut flushed = flushed.into_iter();
+        let closed = self.remove_clients(|_| flushed.next().unwrap(), false);
+        let aborted = self.remove_clients(|_| true, true);
+        ShutdownReport { closed, aborted }
+    }
+
+    /// Disconnect every client for which `keep` returns `false`, without sending anything. This
+    /// can be used to drop clients whose session has expired, or that belong to a banned user.
+    ///
+    /// This function returns the number of clients that were disconnected.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_labeled_client("banned user", client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_labeled_client("good user", client);
+    ///
+    /// server.retain_clients(|client| client.label.as_deref() != Some("banned user"));
+    /// assert_eq!(server.connections(), 1);
+    /// ```
+    pub fn retain_clients(&mut self, mut keep: impl FnMut(&ClientMeta) -> bool) -> usize {
+        self.remove_clients(|client| !keep(&client.meta), true)
+    }
+
+    /// Remove every client whose connection has closed, without sending anything, and call
+    /// `on_prune` with the id of each one. Broadcasting already prunes disconnected clients, so
+    /// this is for periodic maintenance of servers that may go a long time without sending.
+    ///
+    /// Each client is checked by polling whether it is ready to be sent to, so this never waits.
+    /// Clients that are backpressured or failed with a transient error are kept.
+    ///
+    /// This function returns the number of clients that were pruned.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let gone = server.add_client(client);
+    /// drop(body);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// let mut pruned = Vec::new();
+    /// assert_eq!(server.prune(|id| pruned.push(id)), 1);
+    /// assert_eq!(pruned, [gone]);
+    /// assert_eq!(server.connections(), 1);
+    /// ```
+    pub fn prune(&mut self, mut on_prune: impl FnMut(ClientId)) -> usize {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let closed: Vec<bool> = self
+            .clients
+            .iter_mut()
+            .map(|client| {
+                matches!(client.sender.poll_ready(&mut cx), Poll::Ready(Err(SinkError::Closed)))
+            })
+            .collect();
+        let mut closed = closed.into_iter();
+        self.remove_clients(|client| {
+            let closed = closed.next().unwrap();
+            if closed {
+                on_prune(client.meta.id);
+            }
+            closed
+        }, false)
+    }
+
+    /// Remove every client whose connection has definitely closed, like [`prune`](#method.prune),
+    /// and return their ids, such as for logging. Clients that are merely slow are kept. This never
+    /// waits, so it is cheap enough to run often.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, body) = hyper::Body::channel();
+    /// let gone = server.add_client(client);
+    /// drop(body);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    ///
+    /// assert_eq!(server.drain_closed(), [gone]);
+    /// assert_eq!(server.connections(), 1);
+    /// ```
+    pub fn drain_closed(&mut self) -> Vec<ClientId> {
+        let mut closed = Vec::new();
+        self.prune(|id| closed.push(id));
+        closed
+    }
+
+    /// Move every client for which `pred` returns `true` into a new server, and return it. This can
+    /// be used to shard a server, such as to move all the clients of one tenant to a dedicated
+    /// server.
+    ///
+    /// The clients keep their ids and metadata, and the two servers share the counter they hand
+    /// out ids from, so ids stay unique between them. Only the connections are moved: events
+    /// that are being broadcast to the clients aren't transferred and the new server otherwise has
+    /// the default configuration. No callbacks are run, as the clients haven't disconnected.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// let moved = server.add_labeled_client("tenant a", client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_labeled_client("tenant b", client);
+    ///
+    /// let mut tenant_a = server.split(|client| client.label.as_deref() == Some("tenant a"));
+    /// assert_eq!(server.connections(), 1);
+    /// assert_eq!(tenant_a.connections(), 1);
+    /// assert!(tenant_a.remove_client(moved));
+    ///
+    /// let (client, _body) = hyper::Body::channel();
+    /// let a = tenant_a.add_client(client);
+    /// let (client, _body) = hyper::Body::channel();
+    /// let b = server.add_client(client);
+    /// assert_ne!(a, b);
+    /// ```
+    pub fn split(&mut self, mut pred: impl FnMut(&ClientMeta) -> bool) -> Server<S> {
+        let (moved, kept) = std::mem::take(&mut self.clients)
+            .into_iter()
+            .partition::<Vec<_>, _>(|client| pred(&client.meta));
+        self.clients = kept;
+        let resume_points = moved
+            .iter()
+            .filter_map(|client| client.meta.resume_key.as_ref())
+            .filter_map(|key| self.resume_points.get_key_value(key))
+            .map(|(key, id)| (key.clone(), id.clone()))
+            .collect();
+        self.report_connections();
+        let mut split = Server {
+            clients: moved,
+            next_id: Arc::clone(&self.next_id),
+            resume_points,
+            ..Server::default()
+        };
+        split.report_connections();
+        split
+    }
+
+    /// Set a callback to run for every client that is removed from the server, whether it was
+    /// pruned after disconnecting or was disconnected by the server.
+    pub fn on_disconnect(&mut self, callback: impl Fn(ClientId) + Send + 'static) {
+        self.on_disconnect = Some(Box::new(callback));
+    }
+
+    /// Count the number of currently held connections.
+    ///
+    /// Note that this may be an over-estimate of the number of currently connected clients, as
+    /// some clients may have disconnected since the last `send_to_clients` or `send_heartbeat`
+    /// (both of which prune the list of connections to those which still have a connected client).
+    pub fn connections(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Take a snapshot of the server's clients, for tests to check that concurrent operations
+    /// left it consistent. This needs the `test-util` feature.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # use std::sync::Arc;
+    /// # use tokio::sync::Mutex;
+    /// # use hyper_usse::EventBuilder;
+    /// let server = Arc::new(Mutex::new(hyper_usse::Server::new()));
+    /// let adders: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let server = Arc::clone(&server);
+    ///         tokio::spawn(async move {
+    ///             let (client, body) = hyper::Body::channel();
+    ///             server.lock().await.add_client(client);
+    ///             body
+    ///         })
+    ///     })
+    ///     .collect();
+    /// let _bodies = futures::future::join_all(adders).await;
+    ///
+    /// let snapshot = server.lock().await.snapshot();
+    /// assert_eq!(snapshot.connections, 4);
+    /// assert_eq!(snapshot.ids.len(), 4);
+    /// assert!(!snapshot.unsettled);
+    /// # }
+    /// ```
+    #[cfg(feature = "test-util")]
+    pub fn snapshot(&self) -> crate::test_util::Snapshot {
+        crate::test_util::Snapshot {
+            connections: self.clients.len(),
+            ids: self.clients.iter().map(|client| client.meta.id).collect(),
+            unsettled: self.unsettled,
+        }
+    }
+
+    /// Iterate over the ids and metadata of the clients, in the order they were added. This is
+    /// also what iterating over `&Server` does.
+    ///
+    /// Like [`connections`](#method.connections), this can include clients that have disconnected
+    /// since they were last sent something.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// let id = server.add_labeled_client("alice", client);
+    ///
+    /// for (client_id, meta) in &server {
+    ///     assert_eq!(client_id, id);
+    ///     assert_eq!(meta.label.as_deref(), Some("alice"));
+    /// }
+    /// assert_eq!(server.iter().count(), 1);
+    /// ```
+    pub fn iter(&self) -> Clients<'_, S> {
+        Clients(self.clients.iter())
+    }
+
+    /// Get the clients whose most recent broadcast took longer than `threshold` to be accepted by
+    /// their connection. These clients are backpressured: they aren't reading the events as fast
+    /// as they are sent, and will fall further behind or be dropped if that continues. They can be
+    /// evicted with [`retain_clients`](#method.retain_clients).
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use std::time::Duration;
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// server.send_to_clients(EventBuilder::new("Hello")).await;
+    ///
+    /// assert!(server.slow_clients(Duration::from_secs(1)).is_empty());
+    /// # });
+    /// ```
+    pub fn slow_clients(&self, threshold: Duration) -> Vec<ClientId> {
+        self.clients
+            .iter()
+            .filter(|client| client.meta.last_send_latency.is_some_and(|latency| latency > threshold))
+            .map(|client| client.meta.id)
+            .collect()
+    }
+
+    /// Reserve space for at least `additional` more clients, so that a surge of connections, such
+    /// as every client reconnecting after a deploy, doesn't repeatedly reallocate the server's list
+    /// of clients. This only affects that list, not any buffers of the clients themselves.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut server = hyper_usse::Server::new();
+    /// server.reserve(1000);
+    /// assert!(server.capacity() >= 1000);
+    /// ```
+    pub fn reserve(&mut self, additional: usize) {
+        self.clients.reserve(additional);
+    }
+
+    /// Get the number of clients the server can hold without reallocating its list of clients.
+    pub fn capacity(&self) -> usize {
+        self.clients.capacity()
+    }
+
+    /// Count the number of currently held connections subscribed to `topic`.
+    ///
+    /// Like [`connections`](#method.connections), this may be an over-estimate, as it only
+    /// reflects the clients pruned by the last send to each of them.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut server = hyper_usse::Server::new();
+    /// let (sports, _sports_body) = hyper::Body::channel();
+    /// let (news, news_body) = hyper::Body::channel();
+    /// server.add_client_to_topic("sports", sports);
+    /// server.add_client_to_topic("news", news);
+    /// assert_eq!(server.topics(), vec!["news", "sports"]);
+    ///
+    /// drop(news_body);
+    /// assert_eq!(server.connections_in_topic("news"), 1);
+    /// server.send_to_topic("news", EventBuilder::new("Extra!")).await;
+    /// assert_eq!(server.connections_in_topic("news"), 0);
+    /// assert_eq!(server.topics(), vec!["sports"]);
+    /// # });
+    /// ```
+    pub fn connections_in_topic(&self, topic: &str) -> usize {
+        self.clients.iter().filter(|client| client.meta.topic.as_deref() == Some(topic)).count()
+    }
+
+    /// Get the topics that currently held connections are subscribed to, in sorted order.
+    pub fn topics(&self) -> Vec<&str> {
+        let mut topics: Vec<&str> = self.clients
+            .iter()
+            .filter_map(|client| client.meta.topic.as_deref())
+            .collect();
+        topics.sort_unstable();
+        topics.dedup();
+        topics
+    }
+
+    /// Get the connection counts recorded after the most recent broadcasts, oldest first, if
+    /// [`with_connection_history`](#method.with_connection_history) was used. This is useful for
+    /// drawing graphs of the connection count without an external metrics system.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new().with_connection_history(2);
+    /// let (client, body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// server.send_heartbeat().await;
+    /// drop(body);
+    /// server.send_heartbeat().await;
+    /// server.send_heartbeat().await;
+    ///
+    /// let counts: Vec<usize> = server.connection_history().iter().map(|&(_, count)| count).collect();
+    /// assert_eq!(counts, vec![0, 0]);
+    /// # });
+    /// ```
+    pub fn connection_history(&self) -> &[(Instant, usize)] {
+        &self.history[self.history.len().saturating_sub(self.history_window)..]
+    }
+
+    /// Get statistics about everything the server has sent.
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// let mut server = hyper_usse::Server::new();
+    /// let (client, _body) = hyper::Body::channel();
+    /// server.add_client(client);
+    /// server.send_heartbeat().await;
+    ///
+    /// let stats = server.stats();
+    /// assert_eq!(stats.events_broadcast, 1);
+    /// assert_eq!(stats.bytes_sent, 3);
+    /// # });
+    /// ```
+    pub fn stats(&self) -> ServerStats {
+        self.stats
+    }
+
+    /// Export the state needed to resume the clients in another process, for restarts without
+    /// losing events. The connections themselves can't be handed over, so the clients have to
+    /// reconnect to the new process, which then replays the events they missed from the imported
+    /// replay buffer when they are added with
+    /// [`add_client_with_last_id`](#method.add_client_with_last_id) or
+    /// [`register_resumable`](#method.register_resumable).
+    ///
+    /// # Examples
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// # use hyper_usse::EventBuilder;
+    /// let mut old = hyper_usse::Server::new().with_replay_buffer(8);
+    /// old.send_to_clients(EventBuilder::new("First").id("1")).await;
+    /// old.send_to_clients(EventBuilder::new("Second").id("2")).await;
+    ///
+    /// let mut new = hyper_usse::Server::new().with_replay_buffer(8);
+    /// new.import_state(old.export_state());
+    ///
+    /// let (client, body) = hyper::Body::channel();
+    /// new.add_client_with_last_id(client, Some("1"));
+    /// drop(new);
+    /// assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "id: 2\ndata: Second\n\n");
+    /// # });
+    /// ```
+    pub fn export_state(&self) -> ServerState {
+        ServerState {
+            clients: self.clients.iter().map(|client| client.meta.id).collect(),
+            replay: self
+                .replay
+                .iter()
+                .map(|(id, event, _)| (id.clone(), event.slice(..)))
+                .collect(),
+            resume_points: self.resume_points.clone(),
+        }
+    }
+
+    /// Import the state exported from another server by [`export_state`](#method.export_state).
+    ///
+    /// The replay buffer is replaced by the imported one, keeping only the newest events if there
+    /// are more than fit in this server's replay buffer. The imported events count as sent now for
+    /// the replay TTL. The resume points are added to this server's, and ids given to new clients
+    /// won't clash with the imported clients' ids.
+    pub fn import_state(&mut self, state: ServerState) {
+        let skip = state.replay.len().saturating_sub(self.replay_capacity);
+        let now = Instant::now();
+        self.replay = state
+            .replay
+            .into_iter()
+            .skip(skip)
+            .map(|(id, event)| (id, event, now))
+            .collect();
+        self.resume_points.extend(state.resume_points);
+        if let Some(last) = state.clients.iter().max() {
+            self.next_id.fetch_max(last.0 + 1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the number of clients that can currently be sent data without waiting, as opposed to
+    /// those whose connection is backed up because they aren't keeping up with the events.
+    ///
+    /// Comparing this with [`connections`](#method.connections) shows how many clients are
+    /// backpressured. Clients whose connection has closed are not counted, but aren't removed.
+    pub fn active_connections(&mut self) -> usize {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        self.clients
+            .iter_mut()
+            .map(|client| readiness(&mut client.sender, &mut cx))
+            .filter(|&readiness| readiness == Readiness::Ready)
+            .count()
+    }
+
+    /// Remove every client for which `remove` returns `true`, keeping the order of the others, and
+    /// run the callbacks. When `abort` is set the removed clients' connections are aborted,
+    /// otherwise they are just dropped.
+    ///
+    /// This function returns the number of clients that were removed.
+    fn remove_clients(&mut self, mut remove: impl FnMut(&Client<S>) -> bool, abort: bool) -> usize {
+        let before = self.clients.len();
+        let mut kept = 0;
+        for i in 0..self.clients.len() {
+            if !remove(&self.clients[i]) {
+                self.clients.swap(kept, i);
+                kept += 1;
+            }
+        }
+        let removed = self.clients.split_off(kept);
+        let count = removed.len();
+        #[cfg(feature = "metrics")]
+        metrics::counter!("sse_clients_dropped_total").increment(count as u64);
+        self.report_connections();
+        for client in removed {
+            if let Some(on_disconnect) = &self.on_disconnect {
+                on_disconnect(client.meta.id);
+            }
+            if abort {
+                client.sender.abort();
+            }
+        }
+        self.clients_removed(before);
+        count
+    }
+
+    /// Report the number of clients to the `metrics` facade, if that feature is enabled.
+    fn report_connections(&mut self) {
+        #[cfg(feature = "metrics")]
+        self.connection_gauge.update(self.clients.len());
+    }
+
+    /// Run the `on_empty` callback if removing clients took the server from `before` clients to
+    /// none.
+    fn clients_removed(&self, before: usize) {
+        if before > 0 && self.clients.is_empty() {
+            if let Some(on_empty) = &self.on_empty {
+                on_empty();
+            }
+        }
+    }
+}
+
+/// Get a random seed for a server's random number generator.
+fn random_seed() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    RandomState::new().build_hasher().finish()
+}
+
+/// Get a random number in `0.0..1.0` from a SplitMix64 generator, advancing its state.
+fn next_random(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether a client should be sent heartbeats, given whether they are only sent to HTTP/1 clients.
+fn wants_heartbeat<S>(client: &Client<S>, http1_only: bool) -> bool {
+    !http1_only || client.meta.version.map_or(true, |version| version < Version::HTTP_2)
+}
+
+/// The number of a server's clients that are counted in the `sse_clients_connected` gauge. They
+/// are uncounted when the server is dropped.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct ConnectionGauge(usize);
+
+#[cfg(feature = "metrics")]
+impl ConnectionGauge {
+    /// Update the gauge after the server's number of clients changed to `connections`.
+    fn update(&mut self, connections: usize) {
+        let gauge = metrics::gauge!("sse_clients_connected");
+        if connections > self.0 {
+            gauge.increment((connections - self.0) as f64);
+        } else {
+            gauge.decrement((self.0 - connections) as f64);
+        }
+        self.0 = connections;
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for ConnectionGauge {
+    fn drop(&mut self) {
+        self.update(0);
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Server<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Server");
+        debug
+            .field("name", &self.name)
+            .field("clients", &self.clients)
+            .field("next_id", &self.next_id)
+            .field("track_acks", &self.track_acks)
+            .field("replay", &self.replay)
+            .field("replay_capacity", &self.replay_capacity)
+            .field("replay_ttl", &self.replay_ttl)
+            .field("resume_points", &self.resume_points)
+            .field("default_event_type", &self.default_event_type)
+            .field("event_type_prefix", &self.event_type_prefix)
+            .field("on_empty", &self.on_empty.is_some())
+            .field("on_first_client", &self.on_first_client.is_some())
+            .field("on_disconnect", &self.on_disconnect.is_some())
+            .field("client_added", &self.client_added)
+            .field("send_retries", &self.send_retries)
+            .field("scratch", &self.scratch)
+            .field("stats", &self.stats)
+            .field("history", &self.history)
+            .field("history_window", &self.history_window)
+            .field("staged", &self.staged)
+            .field("debounced", &self.debounced)
+            .field("heartbeat", &self.heartbeat)
+            .field("max_event_size", &self.max_event_size)
+            .field("max_clients", &self.max_clients)
+            .field("strict", &self.strict)
+            .field("unsettled", &self.unsettled)
+            .field("unterminated", &self.unterminated)
+            .field("http1_heartbeats_only", &self.http1_heartbeats_only)
+            .field("rng", &self.rng)
+            .field("buckets", &self.buckets)
+            .field("backpressure", &self.backpressure)
+            .field("timestamp_ids", &self.timestamp_ids)
+            .field("last_timestamp", &self.last_timestamp)
+            .field("debounce_staged", &self.debounce_staged)
+            .field("content_hash", &self.content_hash);
+        #[cfg(feature = "metrics")]
+        debug.field("connection_gauge", &self.connection_gauge);
+        debug.finish()
+    }
+}
+
+impl<S> Display for Server<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "{}: ", name)?;
+        }
+        write!(
+            f,
+            "{} connections, {} events broadcast, {} bytes sent",
+            self.clients.len(),
+            self.stats.events_broadcast,
+            self.stats.bytes_sent,
+        )
+    }
+}
+
+impl<'a, S: EventSink> IntoIterator for &'a Server<S> {
+    type Item = (ClientId, &'a ClientMeta);
+    type IntoIter = Clients<'a, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the ids and metadata of the clients of a [Server](struct.Server.html), from
+/// [`Server::iter`](struct.Server.html#method.iter).
+pub struct Clients<'a, S = Sender>(std::slice::Iter<'a, Client<S>>);
+
+impl<'a, S> Iterator for Clients<'a, S> {
+    type Item = (ClientId, &'a ClientMeta);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|client| (client.meta.id, &client.meta))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<S> DoubleEndedIterator for Clients<'_, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|client| (client.meta.id, &client.meta))
+    }
+}
+
+impl<S> ExactSizeIterator for Clients<'_, S> {}
+
+impl<S> Clone for Clients<'_, S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S> fmt::Debug for Clients<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone().map(|(_, meta)| meta)).finish()
+    }
+}
+
+/// An event that is being streamed to the clients of a [Server](struct.Server.html).
+///
+/// Created by [`Server::open_event`](struct.Server.html#method.open_event). While it exists the
+/// server is borrowed, so no clients can be added part way through the event. If it is dropped
+/// without calling `close`, the next broadcast first sends the blank line that ends the event, so
+/// that it doesn't become part of it.
+#[derive(Debug)]
+pub struct OpenEvent<'a, S = Sender> {
+    server: &'a mut Server<S>,
+    /// Whether the blank line ending the event has been sent.
+    closed: bool,
+}
+
+impl<'a, S: EventSink> OpenEvent<'a, S> {
+    /// Send some data to the clients. Each line of the data is sent as its own `data:` line.
+    /// This will automatically remove all disconnected clients.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn send_data(&mut self, data: &str) -> usize {
+        for line in lines(data) {
+            let mut frame = String::with_capacity(DATA_FIELD.len() + line.len() + 1);
+            frame.push_str(DATA_FIELD);
+            frame.push_str(line);
+            frame.push('\n');
+            self.server.send_partial(frame).await;
+        }
+        self.server.connections()
+    }
+
+    /// Close the event by sending the blank line, which makes the clients dispatch it.
+    ///
+    /// This function returns the number of currently connected clients.
+    pub async fn close(mut self) -> usize {
+        let connections = self.server.send_partial("\n").await;
+        self.closed = true;
+        connections
+    }
+}
+
+impl<S> Drop for OpenEvent<'_, S> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.server.unterminated = true;
+        }
+    }
+}
+
+/// A broadcast in progress, created by
+/// [`Server::send_to_clients_with_progress`](struct.Server.html#method.send_to_clients_with_progress).
+///
+/// This is a stream of the ids of the clients that have been sent the data, in the order the sends
+/// complete. The clients stay in the server while it exists, and the outcomes of the sends are
+/// recorded when it ends or is dropped.
+#[must_use = "streams do nothing unless polled"]
+pub struct SendProgress<'a, S: EventSink> {
+    server: &'a mut Server<S>,
+    /// The send to each of the server's clients, in the same order, until it has finished.
+    sends: Vec<Option<ClientSend>>,
+    /// The number of sends that haven't finished.
+    pending: usize,
+    /// The clients that have been sent the data but haven't been yielded yet.
+    sent: VecDeque<ClientId>,
+    finished: bool,
+}
+
+impl<S: EventSink> SendProgress<'_, S> {
+    /// Record the outcomes of the sends. The clients that haven't been sent the data yet stay
+    /// connected.
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.server.settle(true);
+        self.server.sample_history();
+    }
+}
+
+impl<S: EventSink> Stream for SendProgress<'_, S> {
+    type Item = ClientId;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ClientId>> {
+        let this = &mut *self;
+        if let Some(id) = this.sent.pop_front() {
+            return Poll::Ready(Some(id));
+        }
+        for (slot, client) in this.sends.iter_mut().zip(&mut this.server.clients) {
+            let send = match slot {
+                Some(send) => send,
+                None => continue,
+            };
+            if send.poll(client, cx).is_ready() {
+                *slot = None;
+                this.pending -= 1;
+                if matches!(client.outcome, Some((_, true))) {
+                    this.sent.push_back(client.meta.id);
+                }
+            }
+        }
+        if let Some(id) = this.sent.pop_front() {
+            Poll::Ready(Some(id))
+        } else if this.pending == 0 {
+            this.finish();
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S: EventSink> Drop for SendProgress<'_, S> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl<S: EventSink + fmt::Debug> fmt::Debug for SendProgress<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("SendProgress")
+            .field("server", &self.server)
+            .field("pending", &self.pending)
+            .field("sent", &self.sent)
+            .finish()
+    }
+}
@@ -0,0 +1,207 @@
+//! Connections that a server can send events to.
+use futures::future;
+use hyper::body::{Bytes, Sender};
+use hyper::HeaderMap;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time;
+
+/// A connection that a [Server](struct.Server.html) can send events to.
+///
+/// This is implemented for Hyper's body [`Sender`], and can be implemented for other transports,
+/// such as WebSockets or in-memory clients for tests.
+///
+/// # Examples
+/// ```
+/// # futures::executor::block_on(async {
+/// use std::task::{Context, Poll};
+/// use hyper::body::Bytes;
+/// use hyper_usse::{EventBuilder, EventSink, Server, SinkError};
+///
+/// #[derive(Debug, Default)]
+/// struct Recorder(Vec<Bytes>);
+///
+/// impl EventSink for Recorder {
+///     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), SinkError>> {
+///         Poll::Ready(Ok(()))
+///     }
+///     fn start_send(&mut self, bytes: Bytes) -> Result<(), SinkError> {
+///         self.0.push(bytes);
+///         Ok(())
+///     }
+///     fn abort(self) {}
+/// }
+///
+/// let mut server = Server::<Recorder>::default();
+/// server.add_client(Recorder::default());
+/// assert_eq!(server.send_to_clients(EventBuilder::new("Hello")).await, 1);
+/// # });
+/// ```
+pub trait EventSink {
+    /// Check whether the sink can be sent more bytes, registering the task to be woken once it can
+    /// if it can't yet. Returns an error if the sink can never be sent bytes again.
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SinkError>>;
+
+    /// Send bytes to the sink. This is only called after `poll_ready` has returned `Ok`.
+    fn start_send(&mut self, bytes: Bytes) -> Result<(), SinkError>;
+
+    /// Close the sink abruptly, so that the client sees an error instead of the end of the stream.
+    fn abort(self);
+
+    /// End the sink's stream with HTTP trailers. Sinks that can't send trailers, such as Hyper
+    /// 0.13's body [`Sender`], return [`SinkError::Unsupported`](enum.SinkError.html), which is
+    /// what this does unless it is overridden.
+    fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), SinkError> {
+        drop(trailers);
+        Err(SinkError::Unsupported)
+    }
+
+    /// Send bytes to the sink, waiting until it is ready for them.
+    fn send_bytes(&mut self, bytes: Bytes) -> SendBytes<'_, Self> {
+        SendBytes { sink: self, bytes: Some(bytes) }
+    }
+}
+
+impl EventSink for Sender {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SinkError>> {
+        Sender::poll_ready(self, cx).map_err(|_| SinkError::Closed)
+    }
+    fn start_send(&mut self, bytes: Bytes) -> Result<(), SinkError> {
+        self.try_send_data(bytes).map_err(|_| SinkError::Closed)
+    }
+    fn abort(self) {
+        Sender::abort(self);
+    }
+}
+
+/// An error sending bytes to an [EventSink](trait.EventSink.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SinkError {
+    /// The sink's connection has closed, so it will never accept bytes again.
+    Closed,
+    /// Sending failed for a reason that may not happen again, so it can be retried.
+    Transient,
+    /// The sink can't send what it was asked to, such as trailers.
+    Unsupported,
+}
+
+impl Display for SinkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "the connection has closed",
+            Self::Transient => "sending failed",
+            Self::Unsupported => "the connection can't send this",
+        })
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+/// The future returned by [`EventSink::send_bytes`](trait.EventSink.html#method.send_bytes).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SendBytes<'a, S: ?Sized> {
+    sink: &'a mut S,
+    bytes: Option<Bytes>,
+}
+
+impl<S: EventSink + ?Sized> Future for SendBytes<'_, S> {
+    type Output = Result<(), SinkError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Err(e) = futures::ready!(this.sink.poll_ready(cx)) {
+            return Poll::Ready(Err(e));
+        }
+        let bytes = this.bytes.take().expect("SendBytes polled after completion");
+        Poll::Ready(this.sink.start_send(bytes))
+    }
+}
+
+/// Whether a client can be sent data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Readiness {
+    /// The client can be sent data now.
+    Ready,
+    /// The client is connected but can't accept more data yet.
+    Busy,
+    /// The client's connection has closed.
+    Closed,
+}
+
+/// Check whether a client can be sent data, without waiting.
+pub(crate) fn readiness(sender: &mut impl EventSink, cx: &mut Context<'_>) -> Readiness {
+    match sender.poll_ready(cx) {
+        Poll::Ready(Ok(())) => Readiness::Ready,
+        Poll::Ready(Err(_)) => Readiness::Closed,
+        Poll::Pending => Readiness::Busy,
+    }
+}
+
+/// The backoff after the first failed send; each further attempt waits this much longer.
+const RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Sending data to a sink, retrying transient failures up to `retries` times, as a state machine
+/// that is polled with the sink it sends to. Everything that retries sends is built on this.
+#[derive(Debug)]
+pub(crate) struct RetryingSend {
+    bytes: Bytes,
+    retries: u32,
+    /// The number of failed attempts so far.
+    attempt: u32,
+    /// The wait before the next attempt, after a transient failure.
+    backoff: Option<time::Delay>,
+}
+
+impl RetryingSend {
+    pub(crate) fn new(bytes: Bytes, retries: u32) -> Self {
+        Self { bytes, retries, attempt: 0, backoff: None }
+    }
+
+    /// Get the data being sent.
+    pub(crate) fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+
+    /// Poll sending the data to `sink`, which once finished returns whether the data was sent.
+    pub(crate) fn poll(&mut self, sink: &mut impl EventSink, cx: &mut Context<'_>) -> Poll<bool> {
+        loop {
+            if let Some(backoff) = &mut self.backoff {
+                futures::ready!(Pin::new(backoff).poll(cx));
+                self.backoff = None;
+            }
+            let result = match futures::ready!(sink.poll_ready(cx)) {
+                Ok(()) => sink.start_send(self.bytes.slice(..)),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => return Poll::Ready(true),
+                Err(SinkError::Closed) => return Poll::Ready(false),
+                Err(_) if self.attempt >= self.retries => return Poll::Ready(false),
+                Err(_) => {
+                    self.attempt += 1;
+                    self.backoff = Some(time::delay_for(RETRY_BACKOFF * self.attempt));
+                }
+            }
+        }
+    }
+}
+
+/// Send data to a client, retrying transient failures up to `retries` times. Returns whether the
+/// data was sent.
+pub(crate) async fn send_with_retries(client: &mut impl EventSink, bytes: Bytes, retries: u32) -> bool {
+    let mut send = RetryingSend::new(bytes, retries);
+    future::poll_fn(|cx| send.poll(client, cx)).await
+}
+
+/// Send data to a client if it can accept it without waiting, and otherwise drop the data.
+pub(crate) fn try_send(client: &mut impl EventSink, bytes: Bytes) {
+    let waker = futures::task::noop_waker();
+    if let Poll::Ready(Ok(())) = client.poll_ready(&mut Context::from_waker(&waker)) {
+        let _ = client.start_send(bytes);
+    }
+}
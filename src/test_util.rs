@@ -0,0 +1,73 @@
+//! Utilities for testing applications that use this library. These need the `test-util` feature.
+
+use futures::future::Future;
+use hyper::body::HttpBody;
+use hyper::{Client, Uri};
+use std::time::Duration;
+use tokio::time;
+
+use crate::ClientId;
+
+/// The number of events [`assert_unbuffered`] checks.
+const EVENTS: usize = 3;
+
+/// Check that events sent to an SSE endpoint reach the client straight away, instead of being held
+/// back by a buffering proxy, compression layer or other middleware in front of it.
+///
+/// This makes a request to `uri`, then calls `send_event` a few times to make the server send an
+/// event, typically by broadcasting through the [Server](../struct.Server.html) the endpoint adds
+/// its clients to. Each event has to arrive in full within `timeout` before the next one is sent.
+/// Point `uri` at the proxy to test that a particular deployment doesn't buffer the stream.
+///
+/// This must be called from within a Tokio runtime.
+///
+/// # Panics
+///
+/// Panics if the response headers or any of the events don't arrive within `timeout`, or if the
+/// request fails or the stream ends early.
+pub async fn assert_unbuffered<F, Fut>(uri: Uri, timeout: Duration, mut send_event: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let response = time::timeout(timeout, Client::new().get(uri))
+        .await
+        .expect("the response headers were buffered")
+        .expect("the request failed");
+    let mut body = response.into_body();
+
+    for event in 1..=EVENTS {
+        send_event().await;
+        let read = async {
+            let mut received = Vec::new();
+            while !received.ends_with(b"\n\n") {
+                let chunk = body
+                    .data()
+                    .await
+                    .expect("the stream ended early")
+                    .expect("the stream failed");
+                received.extend_from_slice(&chunk);
+            }
+        };
+        if time::timeout(timeout, read).await.is_err() {
+            panic!("event {} of {} was buffered", event, EVENTS);
+        }
+    }
+}
+
+/// The state of a [Server](../struct.Server.html) at one moment, taken by
+/// [`Server::snapshot`](../struct.Server.html#method.snapshot).
+///
+/// Taking a snapshot borrows the server, so with a server shared behind a lock every field comes
+/// from the same moment, even while other tasks are adding clients and broadcasting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The number of clients the server holds.
+    pub connections: usize,
+    /// The ids of the clients, in the order they were added.
+    pub ids: Vec<ClientId>,
+    /// Whether a broadcast was cancelled and the server hasn't yet recorded which clients it
+    /// reached, which it does at the start of the next broadcast.
+    pub unsettled: bool,
+}
@@ -0,0 +1,100 @@
+// Check how each backpressure strategy treats a client that isn't reading its events.
+use futures::StreamExt;
+use hyper::Body;
+use hyper_usse::{BackpressureStrategy, EventBuilder, Server};
+use std::time::Duration;
+use tokio::time;
+
+#[tokio::test]
+async fn block_waits_for_the_client() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::Block);
+    let (client, _body) = Body::channel();
+    server.add_client(client);
+
+    server.send_to_clients(EventBuilder::new("1")).await;
+    let second = server.send_to_clients(EventBuilder::new("2"));
+    assert!(time::timeout(Duration::from_millis(50), second).await.is_err());
+}
+
+#[tokio::test]
+async fn disconnect_client_removes_the_client() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::DisconnectClient);
+    let (client, mut slow) = Body::channel();
+    server.add_client(client);
+    let (client, mut fast) = Body::channel();
+    server.add_client(client);
+
+    assert_eq!(server.send_to_clients(EventBuilder::new("1")).await, 2);
+    assert_eq!(fast.next().await.unwrap().unwrap(), "data: 1\n\n");
+    assert_eq!(server.send_to_clients(EventBuilder::new("2")).await, 1);
+    assert_eq!(fast.next().await.unwrap().unwrap(), "data: 2\n\n");
+
+    drop(server);
+    assert_eq!(slow.next().await.unwrap().unwrap(), "data: 1\n\n");
+    assert!(slow.next().await.is_none());
+}
+
+#[tokio::test]
+async fn drop_newest_skips_events_for_the_client() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::DropNewest);
+    let (client, mut body) = Body::channel();
+    server.add_client(client);
+
+    for number in 1..=3 {
+        assert_eq!(server.send_to_clients(EventBuilder::new(number.to_string())).await, 1);
+    }
+    assert_eq!(body.next().await.unwrap().unwrap(), "data: 1\n\n");
+
+    server.send_to_clients(EventBuilder::new("4")).await;
+    drop(server);
+    assert_eq!(body.next().await.unwrap().unwrap(), "data: 4\n\n");
+    assert!(body.next().await.is_none());
+}
+
+#[tokio::test]
+async fn parts_of_open_events_are_always_sent() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::DropNewest);
+    let (client, body) = Body::channel();
+    server.add_client(client);
+
+    let producer = async move {
+        let mut event = server.open_event(None, Some("big")).await;
+        event.send_data("line1").await;
+        event.send_data("line2").await;
+        event.close().await;
+    };
+    let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    assert_eq!(received.unwrap(), "event: big\ndata: line1\ndata: line2\n\n");
+}
+
+#[tokio::test]
+async fn dropped_events_keep_the_pending_retry() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::DropNewest);
+    let (client, mut body) = Body::channel();
+    let id = server.add_client(client);
+
+    server.send_to_clients(EventBuilder::new("1")).await;
+    server.set_default_retry_for(id, 5000);
+    server.send_to_clients(EventBuilder::new("2")).await;
+    assert_eq!(body.next().await.unwrap().unwrap(), "data: 1\n\n");
+
+    server.send_to_clients(EventBuilder::new("3")).await;
+    drop(server);
+    assert_eq!(body.next().await.unwrap().unwrap(), "retry: 5000\n\ndata: 3\n\n");
+}
+
+#[tokio::test]
+async fn progress_follows_the_strategy() {
+    let mut server = Server::new().with_backpressure(BackpressureStrategy::DisconnectClient);
+    let (client, _slow) = Body::channel();
+    server.add_client(client);
+    let (client, mut fast) = Body::channel();
+    let fast_id = server.add_client(client);
+
+    server.send_to_clients(EventBuilder::new("1")).await;
+    assert_eq!(fast.next().await.unwrap().unwrap(), "data: 1\n\n");
+    let sent: Vec<_> = server.send_to_clients_with_progress(EventBuilder::new("2")).collect().await;
+    assert_eq!(sent, [fast_id]);
+    assert_eq!(server.connections(), 1);
+    assert_eq!(fast.next().await.unwrap().unwrap(), "data: 2\n\n");
+}
@@ -0,0 +1,108 @@
+// Check that `assert_unbuffered` passes for streams that are sent incrementally and fails for ones
+// held back by a buffering proxy.
+use futures::FutureExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Uri};
+use hyper_usse::test_util::assert_unbuffered;
+use hyper_usse::EventBuilder;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Start an SSE endpoint that adds every request to the returned server.
+fn start_sse() -> (SocketAddr, Arc<Mutex<hyper_usse::Server>>) {
+    let sse = Arc::new(Mutex::new(hyper_usse::Server::new()));
+
+    let service_sse = Arc::clone(&sse);
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(move |_| {
+        let sse = Arc::clone(&service_sse);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_: Request<Body>| {
+                let sse = Arc::clone(&sse);
+                async move {
+                    let (channel, body) = Body::channel();
+                    sse.lock().await.add_client(channel);
+                    Ok::<_, Infallible>(hyper_usse::response(body))
+                }
+            }))
+        }
+    }));
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, sse)
+}
+
+/// Start a TCP proxy to `upstream`. Requests are forwarded straight away, but if `buffer_size` is
+/// set responses are only forwarded once that many bytes have been received, like a badly
+/// configured reverse proxy.
+async fn start_proxy(upstream: SocketAddr, buffer_size: Option<usize>) -> SocketAddr {
+    let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (client, _) = listener.accept().await.unwrap();
+            let server = TcpStream::connect(upstream).await.unwrap();
+            let (mut client_read, mut client_write) = tokio::io::split(client);
+            let (mut server_read, mut server_write) = tokio::io::split(server);
+            tokio::spawn(async move {
+                let _ = tokio::io::copy(&mut client_read, &mut server_write).await;
+            });
+            tokio::spawn(async move {
+                let mut buffered = Vec::new();
+                let mut chunk = [0; 1024];
+                loop {
+                    let read = server_read.read(&mut chunk).await.unwrap_or(0);
+                    buffered.extend_from_slice(&chunk[..read]);
+                    if read == 0 || buffered.len() >= buffer_size.unwrap_or(0) {
+                        if client_write.write_all(&buffered).await.is_err() {
+                            break;
+                        }
+                        buffered.clear();
+                    }
+                    if read == 0 {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn uri(addr: SocketAddr) -> Uri {
+    format!("http://{}/sse", addr).parse().unwrap()
+}
+
+async fn check(addr: SocketAddr, sse: &Mutex<hyper_usse::Server>) {
+    assert_unbuffered(uri(addr), TIMEOUT, || async {
+        sse.lock().await.send_to_clients(EventBuilder::new("event")).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn direct() {
+    let (addr, sse) = start_sse();
+    check(addr, &sse).await;
+}
+
+#[tokio::test]
+async fn streaming_proxy() {
+    let (addr, sse) = start_sse();
+    let proxy = start_proxy(addr, None).await;
+    check(proxy, &sse).await;
+}
+
+#[tokio::test]
+async fn buffering_proxy() {
+    let (addr, sse) = start_sse();
+    let proxy = start_proxy(addr, Some(16 * 1024)).await;
+    let result = AssertUnwindSafe(check(proxy, &sse)).catch_unwind().await;
+    assert!(result.is_err(), "buffering went undetected");
+}
@@ -0,0 +1,101 @@
+// Check that a broadcast that is cancelled part way through leaves the server consistent.
+use futures::StreamExt;
+use hyper::Body;
+use hyper_usse::{BackpressureStrategy, EventBuilder, Server};
+use std::time::Duration;
+use tokio::time;
+
+#[tokio::test]
+async fn cancelled_broadcast_is_recorded_by_next_one() {
+    let mut server = Server::new()
+        .with_ack_tracking()
+        .with_replay_buffer(8)
+        .with_backpressure(BackpressureStrategy::Block);
+    let (client, mut fast) = Body::channel();
+    let fast_id = server.add_client(client);
+    let (client, slow) = Body::channel();
+    server.add_client(client);
+
+    assert_eq!(server.send_to_clients(EventBuilder::new("first").id("1")).await, 2);
+    assert_eq!(fast.next().await.unwrap().unwrap(), "id: 1\ndata: first\n\n");
+
+    // The slow client hasn't read the first event, so sending to it blocks until the broadcast is
+    // cancelled. The fast client is still sent the event in full.
+    let second = server.send_to_clients(EventBuilder::new("second").id("2"));
+    assert!(time::timeout(Duration::from_millis(50), second).await.is_err());
+    assert_eq!(fast.next().await.unwrap().unwrap(), "id: 2\ndata: second\n\n");
+
+    // The next broadcast records that the fast client was sent the second event, and prunes the
+    // slow client once it disconnects.
+    drop(slow);
+    assert_eq!(server.send_to_clients(EventBuilder::new("third").id("3")).await, 1);
+    assert_eq!(server.pending_events(fast_id).len(), 3);
+    assert_eq!(server.stats().events_broadcast, 3);
+
+    // The cancelled event was still added to the replay buffer.
+    let (client, replayed) = Body::channel();
+    server.add_client_with_last_id(client, Some("1"));
+    drop(server);
+    let replayed = hyper::body::to_bytes(replayed).await.unwrap();
+    assert_eq!(replayed, "id: 2\ndata: second\n\nid: 3\ndata: third\n\n");
+}
+
+#[tokio::test]
+async fn dropped_progress_stream_still_records_the_event() {
+    let mut server = Server::new().with_replay_buffer(8);
+    let (client, _body) = Body::channel();
+    server.add_client(client);
+
+    drop(server.send_to_clients_with_progress(EventBuilder::new("first").id("1")));
+    assert_eq!(server.events_since(None), ["id: 1\ndata: first\n\n"]);
+    assert_eq!(server.connections(), 1);
+}
+
+#[tokio::test]
+async fn leaked_progress_stream_keeps_the_clients() {
+    let mut server = Server::new().with_ack_tracking();
+    let (client, mut body) = Body::channel();
+    let id = server.add_client(client);
+
+    // The clients stay in the server even if the stream is never dropped, and the next broadcast
+    // records what it sent.
+    let mut progress = server.send_to_clients_with_progress(EventBuilder::new("first").id("1"));
+    assert_eq!(progress.next().await, Some(id));
+    std::mem::forget(progress);
+    assert_eq!(body.next().await.unwrap().unwrap(), "id: 1\ndata: first\n\n");
+
+    assert_eq!(server.send_to_clients(EventBuilder::new("second").id("2")).await, 1);
+    assert_eq!(server.pending_events(id).len(), 2);
+}
+
+#[tokio::test]
+async fn dropped_open_event_is_ended_by_next_broadcast() {
+    let mut server = Server::new();
+    let (client, body) = Body::channel();
+    server.add_client(client);
+
+    let producer = async move {
+        let mut event = server.open_event(None, Some("log")).await;
+        event.send_data("partial").await;
+        drop(event);
+        server.send_to_clients(EventBuilder::new("whole")).await;
+    };
+    let (_, received) = futures::join!(producer, hyper::body::to_bytes(body));
+    assert_eq!(received.unwrap(), "event: log\ndata: partial\n\ndata: whole\n\n");
+}
+
+#[tokio::test]
+async fn cancelled_chunked_broadcast_is_ended_by_next_broadcast() {
+    let mut server = Server::new();
+    let (client, mut body) = Body::channel();
+    server.add_client(client);
+
+    // The client doesn't read the first chunk, so sending the second blocks until it is cancelled.
+    let chunked = server.broadcast_chunked("one\ntwo", 1);
+    assert!(time::timeout(Duration::from_millis(50), chunked).await.is_err());
+    assert_eq!(body.next().await.unwrap().unwrap(), "data: one\n");
+
+    server.send_to_clients(EventBuilder::new("whole")).await;
+    drop(server);
+    assert_eq!(body.next().await.unwrap().unwrap(), "\ndata: whole\n\n");
+}
@@ -0,0 +1,62 @@
+// Check that SSE responses are streamed to the client as they are sent.
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request};
+use hyper_usse::EventBuilder;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time;
+
+async fn read_until(stream: &mut TcpStream, terminator: &[u8]) -> String {
+    let read = async {
+        let mut read = Vec::new();
+        while !read.ends_with(terminator) {
+            let mut byte = [0];
+            assert_eq!(stream.read(&mut byte).await.unwrap(), 1, "connection closed");
+            read.push(byte[0]);
+        }
+        String::from_utf8(read).unwrap()
+    };
+    time::timeout(Duration::from_secs(5), read).await.expect("timed out waiting for data")
+}
+
+#[tokio::test]
+async fn events_are_sent_chunked_and_incrementally() {
+    let sse = Arc::new(Mutex::new(hyper_usse::Server::new()));
+
+    let service_sse = Arc::clone(&sse);
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(move |_| {
+        let sse = Arc::clone(&service_sse);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_: Request<Body>| {
+                let sse = Arc::clone(&sse);
+                async move {
+                    let (channel, body) = Body::channel();
+                    sse.lock().await.add_client(channel);
+                    Ok::<_, Infallible>(hyper_usse::response(body))
+                }
+            }))
+        }
+    }));
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /sse HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+
+    let head = read_until(&mut stream, b"\r\n\r\n").await.to_ascii_lowercase();
+    assert!(head.starts_with("http/1.1 200 ok\r\n"), "{}", head);
+    assert!(head.contains("\r\ntransfer-encoding: chunked\r\n"), "{}", head);
+    assert!(head.contains("\r\ncontent-type: text/event-stream; charset=utf-8\r\n"), "{}", head);
+    assert!(!head.contains("\r\ncontent-length:"), "{}", head);
+
+    // Each event must arrive as its own chunk while the connection is still open.
+    assert_eq!(sse.lock().await.send_to_clients(EventBuilder::new("first")).await, 1);
+    assert_eq!(read_until(&mut stream, b"\n\n\r\n").await, "D\r\ndata: first\n\n\r\n");
+
+    assert_eq!(sse.lock().await.send_to_clients(EventBuilder::new("second")).await, 1);
+    assert_eq!(read_until(&mut stream, b"\n\n\r\n").await, "E\r\ndata: second\n\n\r\n");
+}
@@ -0,0 +1,101 @@
+// Check that clients added while events are being broadcast only ever receive whole events.
+use hyper::body::HttpBody;
+use hyper::Body;
+use hyper_usse::{EventBuilder, Server};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+const EVENTS: usize = 200;
+const CLIENTS: usize = 50;
+
+/// Read a client's events, checking that every chunk is exactly one whole event and that the
+/// events it was sent are consecutive, ending with the last one broadcast.
+fn read_events(mut body: Body) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut received = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.unwrap();
+            let chunk = std::str::from_utf8(&chunk).unwrap();
+            let number = chunk
+                .strip_prefix("data: ")
+                .and_then(|event| event.strip_suffix("\n\n"))
+                .unwrap_or_else(|| panic!("received a partial event: {:?}", chunk));
+            received.push(number.parse::<usize>().unwrap());
+        }
+        if let Some(&first) = received.first() {
+            assert_eq!(received, (first..EVENTS).collect::<Vec<_>>());
+        }
+    })
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn clients_added_during_broadcasts_receive_whole_events() {
+    let server = Arc::new(Mutex::new(Server::new()));
+
+    let broadcaster = {
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            for number in 0..EVENTS {
+                server.lock().await.send_to_clients(EventBuilder::new(number.to_string())).await;
+            }
+        })
+    };
+    let (readers, mut read) = mpsc::unbounded_channel();
+    let adders: Vec<_> = (0..CLIENTS)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let readers = readers.clone();
+            tokio::spawn(async move {
+                let (client, body) = Body::channel();
+                server.lock().await.add_client(client);
+                readers.send(read_events(body)).unwrap();
+            })
+        })
+        .collect();
+
+    futures::future::try_join_all(adders).await.unwrap();
+    broadcaster.await.unwrap();
+    assert_eq!(server.lock().await.connections(), CLIENTS);
+    drop(server);
+    drop(readers);
+    while let Some(reader) = read.recv().await {
+        reader.await.unwrap();
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn clients_added_to_spawned_server_receive_whole_events() {
+    let server = Server::new().spawn();
+
+    let broadcaster = {
+        let server = server.clone();
+        tokio::spawn(async move {
+            for number in 0..EVENTS {
+                server.broadcast(EventBuilder::new(number.to_string())).await;
+            }
+        })
+    };
+    let (readers, mut read) = mpsc::unbounded_channel();
+    let adders: Vec<_> = (0..CLIENTS)
+        .map(|_| {
+            let server = server.clone();
+            let readers = readers.clone();
+            tokio::spawn(async move {
+                let (client, body) = Body::channel();
+                server.add_client(client).await;
+                readers.send(read_events(body)).unwrap();
+            })
+        })
+        .collect();
+
+    futures::future::try_join_all(adders).await.unwrap();
+    broadcaster.await.unwrap();
+    assert_eq!(server.connections().await, CLIENTS);
+    // The server's task drops the clients once every handle has been dropped.
+    drop(server);
+    drop(readers);
+    while let Some(reader) = read.recv().await {
+        reader.await.unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+// Check when the server notices that clients have disconnected. Disconnections are only detected
+// when something is next sent to the client, by `send_to_clients` or `send_heartbeat`; until then
+// the client is still counted as a connection. Also check how a single client's stream is ended.
+use futures::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::{Body, Request};
+use hyper_usse::{EventBuilder, EventSink, Server, SinkError};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time;
+
+#[tokio::test]
+async fn dropped_body_is_pruned_by_next_send() {
+    let mut server = Server::new();
+    let (client, body) = Body::channel();
+    server.add_client(client);
+
+    drop(body);
+    assert_eq!(server.connections(), 1);
+    assert_eq!(server.send_to_clients(EventBuilder::new("Hello")).await, 0);
+}
+
+#[tokio::test]
+async fn dropped_body_is_pruned_by_next_heartbeat() {
+    let mut server = Server::new();
+    let (client, body) = Body::channel();
+    server.add_client(client);
+
+    drop(body);
+    assert_eq!(server.connections(), 1);
+    assert_eq!(server.send_heartbeat().await, 0);
+}
+
+#[tokio::test]
+async fn body_closed_after_reading_is_pruned_by_next_send() {
+    let mut server = Server::new();
+    let (client, mut body) = Body::channel();
+    server.add_client(client);
+
+    assert_eq!(server.send_to_clients(EventBuilder::new("first")).await, 1);
+    assert_eq!(body.next().await.unwrap().unwrap(), "data: first\n\n");
+    drop(body);
+
+    assert_eq!(server.connections(), 1);
+    assert_eq!(server.send_to_clients(EventBuilder::new("second")).await, 0);
+    assert_eq!(server.connections(), 0);
+}
+
+#[tokio::test]
+async fn closed_connection_is_pruned_only_by_sending() {
+    let sse = Arc::new(Mutex::new(Server::new()));
+
+    let service_sse = Arc::clone(&sse);
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(move |_| {
+        let sse = Arc::clone(&service_sse);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_: Request<Body>| {
+                let sse = Arc::clone(&sse);
+                async move {
+                    let (channel, body) = Body::channel();
+                    sse.lock().await.add_client(channel);
+                    Ok::<_, Infallible>(hyper_usse::response(body))
+                }
+            }))
+        }
+    }));
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /sse HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut head = [0; 1];
+    assert_eq!(stream.read(&mut head).await.unwrap(), 1);
+    drop(stream);
+
+    // Give Hyper plenty of time to notice the closed connection; the client must still be counted.
+    time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(sse.lock().await.connections(), 1);
+
+    // Hyper drops the body once it sees the connection has closed, after which the next heartbeat
+    // prunes the client.
+    let pruned = async {
+        while sse.lock().await.send_heartbeat().await > 0 {
+            time::delay_for(Duration::from_millis(10)).await;
+        }
+    };
+    time::timeout(Duration::from_secs(5), pruned).await.expect("client was never pruned");
+}
+
+/// A sink that records the trailers its stream is ended with.
+#[derive(Debug, Clone, Default)]
+struct TrailerRecorder(Arc<std::sync::Mutex<Option<HeaderMap>>>);
+
+impl EventSink for TrailerRecorder {
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), SinkError>> {
+        Poll::Ready(Ok(()))
+    }
+    fn start_send(&mut self, _: Bytes) -> Result<(), SinkError> {
+        Ok(())
+    }
+    fn abort(self) {}
+    fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), SinkError> {
+        *self.0.lock().unwrap() = Some(trailers);
+        Ok(())
+    }
+}
+
+#[test]
+fn disconnecting_with_trailers_sends_them_and_removes_the_client() {
+    let mut server = Server::<TrailerRecorder>::default();
+    let recorder = TrailerRecorder::default();
+    let id = server.add_client(recorder.clone());
+    server.add_client(TrailerRecorder::default());
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-final-status", HeaderValue::from_static("done"));
+    assert_eq!(server.disconnect_client_with_trailers(id, trailers.clone()), Ok(()));
+    assert_eq!(*recorder.0.lock().unwrap(), Some(trailers));
+    assert_eq!(server.connections(), 1);
+
+    // The client has already gone.
+    assert_eq!(
+        server.disconnect_client_with_trailers(id, HeaderMap::new()),
+        Err(SinkError::Closed),
+    );
+}
@@ -0,0 +1,223 @@
+// Round trip events built by `EventBuilder` through a real SSE parser.
+use eventsource_stream::{Event, Eventsource};
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+use hyper_usse::{BackpressureStrategy, EventBuilder};
+use std::convert::Infallible;
+
+fn parse(framed: String) -> Vec<Event> {
+    let chunks = stream::iter(vec![Ok::<_, Infallible>(framed)]);
+    block_on(chunks.eventsource().map(|event| event.unwrap()).collect())
+}
+
+fn event(data: &str, id: &str, event: &str) -> Event {
+    Event {
+        event: event.to_owned(),
+        data: data.to_owned(),
+        id: id.to_owned(),
+        retry: None,
+    }
+}
+
+#[test]
+fn data_only() {
+    let parsed = parse(EventBuilder::new("Hello").build());
+    assert_eq!(parsed, vec![event("Hello", "", "message")]);
+}
+
+#[test]
+fn all_fields() {
+    let parsed = parse(EventBuilder::new("Hello").id("7").event_type("greeting").build());
+    assert_eq!(parsed, vec![event("Hello", "7", "greeting")]);
+}
+
+#[test]
+fn multi_line_data() {
+    let parsed = parse(EventBuilder::new("one\ntwo\nthree").build());
+    assert_eq!(parsed, vec![event("one\ntwo\nthree", "", "message")]);
+}
+
+#[test]
+fn unicode() {
+    let parsed = parse(EventBuilder::new("héllo wörld 🦀").event_type("ünïcode").build());
+    assert_eq!(parsed, vec![event("héllo wörld 🦀", "", "ünïcode")]);
+}
+
+#[test]
+fn empty_data_does_not_dispatch() {
+    assert_eq!(parse(EventBuilder::new("").build()), vec![]);
+}
+
+#[test]
+fn newline_data_dispatches_empty_data() {
+    assert_eq!(parse(EventBuilder::new("\n").build()), vec![event("", "", "message")]);
+    assert_eq!(parse(EventBuilder::new("\r\n").build()), vec![event("", "", "message")]);
+}
+
+#[test]
+fn blank_lines_data() {
+    assert_eq!(parse(EventBuilder::new("\n\n").build()), vec![event("\n", "", "message")]);
+}
+
+#[test]
+fn whitespace_data() {
+    assert_eq!(parse(EventBuilder::new(" ").build()), vec![event(" ", "", "message")]);
+    assert_eq!(parse(EventBuilder::new(" \n ").build()), vec![event(" \n ", "", "message")]);
+}
+
+#[test]
+fn trailing_newline_is_dropped() {
+    assert_eq!(parse(EventBuilder::new("a\n").build()), vec![event("a", "", "message")]);
+}
+
+#[test]
+fn id_only_does_not_dispatch() {
+    assert_eq!(parse(EventBuilder::new("").id("5").build()), vec![]);
+}
+
+#[test]
+fn id_only_advances_last_event_id() {
+    let framed = EventBuilder::new("first").id("1").build()
+        + &EventBuilder::id_only("5").build()
+        + &EventBuilder::new("second").build();
+    assert_eq!(parse(framed), vec![event("first", "1", "message"), event("second", "5", "message")]);
+}
+
+#[test]
+fn several_events() {
+    let framed = EventBuilder::new("first").id("1").build()
+        + &EventBuilder::new("second").build();
+    // The last event id carries over to events that don't set their own.
+    assert_eq!(parse(framed), vec![event("first", "1", "message"), event("second", "1", "message")]);
+}
+
+#[test]
+fn reset_id() {
+    let framed = EventBuilder::new("first").id("1").build()
+        + &EventBuilder::new("second").reset_id().build()
+        + &EventBuilder::new("third").build();
+    assert_eq!(
+        parse(framed),
+        vec![event("first", "1", "message"), event("second", "", "message"), event("third", "", "message")],
+    );
+}
+
+#[test]
+fn clear_id() {
+    let framed = EventBuilder::new("first").id("1").build()
+        + &EventBuilder::new("second").id("2").clear_id().build();
+    assert_eq!(parse(framed), vec![event("first", "1", "message"), event("second", "1", "message")]);
+}
+
+#[test]
+fn through_server() {
+    let mut server = hyper_usse::Server::new().with_backpressure(BackpressureStrategy::Block);
+    let (client, body) = hyper::Body::channel();
+    server.add_client(client);
+
+    let producer = async move {
+        server.send_to_clients(EventBuilder::new("a\nb").id("1")).await;
+        server.send_heartbeat().await;
+        server.send_to_clients(EventBuilder::new("c").event_type("update")).await;
+    };
+    let consumer = body.eventsource().map(|event| event.unwrap()).collect::<Vec<_>>();
+    let (_, parsed) = block_on(futures::future::join(producer, consumer));
+
+    assert_eq!(parsed, vec![event("a\nb", "1", "message"), event("c", "1", "update")]);
+}
+
+#[test]
+fn open_event_fields_are_single_lines() {
+    let mut server = hyper_usse::Server::new();
+    let (client, body) = hyper::Body::channel();
+    server.add_client(client);
+
+    let producer = async move {
+        let mut event = server.open_event(Some("1\n\ndata: injected\n\n"), Some("a\rb")).await;
+        event.send_data("real").await;
+        event.close().await;
+    };
+    let consumer = body.eventsource().map(|event| event.unwrap()).collect::<Vec<_>>();
+    let (_, parsed) = block_on(futures::future::join(producer, consumer));
+
+    assert_eq!(parsed, vec![event("real", "1data: injected", "ab")]);
+}
+
+#[test]
+fn replay_ids_agree() {
+    let first = "data: a\rid: 5\n\n";
+    let second = "id: 6\ndata: b\n\n";
+    assert_eq!(parse(first.to_owned()), vec![event("a", "5", "message")]);
+
+    let mut server = hyper_usse::Server::new().with_replay_buffer(2);
+    block_on(async {
+        server.send_raw_to_clients(first).await;
+        server.send_raw_to_clients(second).await;
+    });
+    assert_eq!(server.events_since(Some("5")), [second]);
+}
+
+#[test]
+fn parsed_event_agrees() {
+    let framed = EventBuilder::new("a\r\nb\rc").id("7").event_type("update").build()
+        + ": comment\n"
+        + &EventBuilder::new("").build()
+        + &EventBuilder::new(" spaced ").build();
+    let ours: Vec<_> = hyper_usse::ParsedEvent::parse(&framed)
+        .into_iter()
+        .filter(|parsed| !parsed.fields.is_empty())
+        .map(|parsed| (parsed.data, parsed.event_type.unwrap_or_else(|| "message".to_owned())))
+        .collect();
+    let theirs: Vec<_> = parse(framed).into_iter().map(|event| (event.data, event.event)).collect();
+    assert_eq!(ours, theirs);
+}
+
+#[test]
+fn comment_lines_are_ignored() {
+    let trace = "panicked at src/main.rs:1\nevent: bogus\n";
+    let framed = EventBuilder::new("Failed")
+        .append_comment_lines(trace)
+        .append_comment_lines("")
+        .build();
+    assert_eq!(framed, ": panicked at src/main.rs:1\n: event: bogus\ndata: Failed\n\n");
+    let parsed = parse(framed);
+    assert_eq!(parsed, vec![event("Failed", "", "message")]);
+}
+
+#[test]
+fn byte_order_mark_is_stripped_once() {
+    let framed = EventBuilder::new("\u{FEFF}\u{FEFF}a\nb").build();
+    assert_eq!(framed, "data: \u{FEFF}a\ndata: b\n\n");
+    assert_eq!(EventBuilder::new("\u{FEFF}").build(), "\n");
+}
+
+#[test]
+fn will_dispatch_agrees() {
+    let events = vec![
+        EventBuilder::new("Data"),
+        EventBuilder::new("\n"),
+        EventBuilder::new(""),
+        EventBuilder::new("").id("1"),
+        EventBuilder::new("").event_type("update"),
+        EventBuilder::new("").append_comment_lines("comment"),
+        EventBuilder::new("\u{FEFF}"),
+        EventBuilder::new("msg").data_field_name("msg").unwrap(),
+        EventBuilder::new("msg").data_field_name("data").unwrap(),
+        EventBuilder::new("").field("data", "custom").unwrap(),
+    ];
+    for event in events {
+        assert_eq!(event.will_dispatch(), !parse(event.build()).is_empty(), "{:?}", event);
+    }
+}
+
+#[test]
+fn chunked_broadcasts_agree() {
+    let data = "\u{FEFF}one\r\ntwo\rthree\n";
+    let mut server = hyper_usse::Server::new();
+    let (client, body) = hyper::Body::channel();
+    server.add_client(client);
+
+    let producer = async move { server.broadcast_chunked(data, 2).await };
+    let (_, received) = block_on(futures::future::join(producer, hyper::body::to_bytes(body)));
+    assert_eq!(received.unwrap(), EventBuilder::new(data).build());
+}